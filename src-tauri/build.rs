@@ -1,7 +1,23 @@
+// Request messages whose fields a client may leave out, e.g. callers written before the fields existed
+const DEFAULTED_MESSAGES: &[&str] = &[
+    ".unitodo.GetTodosRequest",
+    ".unitodo.AddTodoRequest",
+    ".unitodo.UpdateTodoFieldsRequest",
+    ".unitodo.GetAgendaRequest",
+    ".unitodo.QueryTodosRequest",
+    ".unitodo.GetViewRequest",
+    ".unitodo.GetHistoryRequest",
+    ".unitodo.MigrateFormatRequest",
+];
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tonic_build::configure()
+    let mut builder = tonic_build::configure()
         .protoc_arg("--experimental_allow_proto3_optional")
-        .type_attribute(".", "#[derive(serde::Serialize, serde::Deserialize)]")
+        .type_attribute(".", "#[derive(serde::Serialize, serde::Deserialize)]");
+    for message in DEFAULTED_MESSAGES {
+        builder = builder.message_attribute(message, "#[serde(default)]");
+    }
+    builder
         .build_server(true)
         .build_client(false) // We are building the server in this crate
         .compile(
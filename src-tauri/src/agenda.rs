@@ -0,0 +1,57 @@
+#![allow(clippy::all)]
+use crate::config_models::Config;
+use crate::todo_models::{Agenda, AgendaDay, AgendaEntry, ProcessedTodosOutput};
+use crate::todo_parsing::parse_field_date;
+use crate::todo_processing::compare_todo_content;
+
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use std::collections::{BTreeMap, HashSet};
+
+pub const DEFAULT_AGENDA_DAYS: u32 = 7;
+// Longest range a client may ask for, ten years
+pub const MAX_AGENDA_DAYS: u32 = 3660;
+
+// Groups the open todos of a scan by day, relative to `now` (local time). A todo's day is the earlier of its
+// `@scheduled` and `@due` dates; days before today count as today unless the todo is overdue, and todos dated
+// after the `days` following today are left out.
+pub fn build_agenda(processed: &ProcessedTodosOutput, config: &Config, days: u32, now: &NaiveDateTime) -> Agenda {
+    let today = now.date();
+    let last_day = today.checked_add_signed(Duration::days(days as i64)).unwrap_or(NaiveDate::MAX);
+    let mut agenda = Agenda::default();
+    let mut upcoming: BTreeMap<NaiveDate, Vec<(NaiveDateTime, AgendaEntry)>> = BTreeMap::new();
+    let mut today_entries: Vec<(NaiveDateTime, AgendaEntry)> = Vec::new();
+    let mut overdue_entries: Vec<(NaiveDateTime, AgendaEntry)> = Vec::new();
+
+    let mut seen_locations: HashSet<&str> = HashSet::new();
+    for category in &processed.categories {
+        for item in &category.todos {
+            if !seen_locations.insert(item.location.as_str()) { continue; } // Another project's copy of a todo
+            if config.todo_state_of(&item.status).map_or(false, |s| s.is_closed()) { continue; }
+            let entry = AgendaEntry { item: item.clone(), category: category.name.clone() };
+            let field_date = |name: &str| item.fields.get(name).and_then(|f| parse_field_date(&f.raw));
+            let agenda_date = match (field_date("scheduled"), field_date("due")) {
+                (Some(s), Some(d)) => Some(s.min(d)),
+                (s, d) => s.or(d),
+            };
+            match agenda_date {
+                _ if item.overdue => overdue_entries.push((field_date("due").unwrap_or(*now), entry)),
+                None => agenda.undated.push(entry),
+                Some(date) if date.date() <= today => today_entries.push((date, entry)),
+                Some(date) if date.date() <= last_day => upcoming.entry(date.date()).or_default().push((date, entry)),
+                Some(_) => {}
+            }
+        }
+    }
+
+    let sorted = |mut entries: Vec<(NaiveDateTime, AgendaEntry)>| -> Vec<AgendaEntry> {
+        entries.sort_by(|(a_date, a), (b_date, b)| a_date.cmp(b_date).then_with(|| compare_todo_content(&a.item, &b.item)));
+        entries.into_iter().map(|(_, entry)| entry).collect()
+    };
+    agenda.overdue = sorted(overdue_entries);
+    agenda.today = sorted(today_entries);
+    agenda.upcoming = upcoming.into_iter()
+        .map(|(date, entries)| AgendaDay { date: date.format("%Y-%m-%d").to_string(), entries: sorted(entries) })
+        .collect();
+    agenda.undated.sort_by(|a, b| compare_todo_content(&a.item, &b.item));
+    agenda
+}
@@ -0,0 +1,116 @@
+#![allow(clippy::all)]
+use crate::config_io::load_config_from_file;
+use crate::config_models::{AppConfiguration, Config};
+use crate::format_migration::{migrate_todo_format, MigrationReport};
+use crate::todo_processing::{find_and_process_todos, remove_deferred_todos};
+use crate::todo_query::{parse_query, query_todos};
+use crate::todo_sort::{parse_sort_spec, sort_query_matches};
+
+use chrono::Local;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand};
+use std::io;
+
+// --- Command line interface (running without arguments starts the app) ---
+#[derive(Parser, Debug)]
+#[command(name = "unitodo", version, about = "Unifying distributed TODOs")] // UNITODO_IGNORE_LINE
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<CliCommand>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CliCommand {
+    /// Rewrite todos from short-timestamp first words to @created/@finished fields with nanoid ids
+    MigrateFormat {
+        /// Print the changes without writing any file
+        #[arg(long)]
+        dry_run: bool,
+        /// Config profile whose scan paths are migrated (defaults to the active profile)
+        #[arg(long)]
+        profile: Option<String>,
+    },
+    /// Print the todos matching a query, e.g. 'state:open prio:<=1 text:"login" due:<7d -path:**/vendor/**'
+    Query {
+        /// The query, quoted as one argument
+        query: String,
+        /// Also list todos deferred with @defer(...)
+        #[arg(long)]
+        include_deferred: bool,
+        /// Sort key, e.g. 'priority', 'due desc' or '-created'; repeat for ties
+        #[arg(long)]
+        sort: Vec<String>,
+        /// Config profile whose scan paths are searched (defaults to the active profile)
+        #[arg(long)]
+        profile: Option<String>,
+    },
+}
+
+// Parses the command line only when arguments were given, so a plain launch goes straight to the app. Arguments that
+// aren't a command (the OS may pass a `-psn_...` process id or a deep link) start the app as well; errors are only
+// reported for `--help`, `--version` and the arguments of an actual command.
+pub fn parse_cli_command() -> Option<CliCommand> {
+    if std::env::args_os().len() <= 1 { return None; }
+    let names_command = std::env::args_os().nth(1)
+        .and_then(|arg| arg.to_str().map(|arg| Cli::command().find_subcommand(arg).is_some()))
+        .unwrap_or(false);
+    match Cli::try_parse() {
+        Ok(cli) => cli.command,
+        Err(e) if names_command || matches!(e.kind(), ErrorKind::DisplayHelp | ErrorKind::DisplayVersion) => e.exit(),
+        Err(e) => {
+            log::info!("Arguments are not a command, starting the app: {}", e.kind());
+            None
+        }
+    }
+}
+
+pub fn run_cli_command(command: CliCommand) -> io::Result<()> {
+    match command {
+        CliCommand::MigrateFormat { dry_run, profile } => {
+            let app_config = load_config_from_file()?;
+            let config = profile_config(&app_config, profile)?;
+            let report = migrate_todo_format(config, dry_run)?;
+            print_migration_report(&report);
+            if report.files_failed > 0 {
+                return Err(io::Error::new(io::ErrorKind::Other, format!("{} file(s) could not be migrated", report.files_failed)));
+            }
+            Ok(())
+        }
+        CliCommand::Query { query, include_deferred, sort, profile } => {
+            let query = parse_query(&query, &Local::now().naive_local())?;
+            let sort_keys = parse_sort_spec(&sort)?;
+            let app_config = load_config_from_file()?;
+            let config = profile_config(&app_config, profile)?;
+            let mut processed = find_and_process_todos(config, false)?;
+            if !include_deferred && !query.asks_for_deferred() { remove_deferred_todos(&mut processed.categories); }
+            let mut matches = query_todos(&processed, config, &query);
+            sort_query_matches(&mut matches, &sort_keys);
+            for query_match in matches {
+                println!("{}\t{}\t{} {}", query_match.item.location, query_match.category, query_match.item.status.trim_end(), query_match.item.content);
+            }
+            Ok(())
+        }
+    }
+}
+
+fn profile_config(app_config: &AppConfiguration, profile: Option<String>) -> io::Result<&Config> {
+    let profile_name = profile.unwrap_or_else(|| app_config.active_profile.clone());
+    app_config.profiles.get(&profile_name)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("Profile '{}' not found.", profile_name)))
+}
+
+fn print_migration_report(report: &MigrationReport) {
+    for file in &report.files {
+        match &file.error {
+            Some(error) => eprintln!("{}: {}", file.path, error),
+            None if report.dry_run => print!("--- {}\n+++ {}\n{}", file.path, file.path, file.diff),
+            None => println!("{}: {} todo(s) migrated", file.path, file.todos_migrated),
+        }
+    }
+    println!(
+        "{}{} of {} todo(s) migrated in {} file(s): {} id(s) assigned, {} timestamp(s) decoded, {} skipped (changed since scan), {} file(s) failed",
+        if report.dry_run { "[dry run] " } else { "" },
+        report.todos_migrated, report.todos_scanned, report.files_changed,
+        report.ids_assigned, report.timestamps_decoded, report.todos_skipped, report.files_failed,
+    );
+}
@@ -14,6 +14,11 @@ pub fn get_primary_config_path() -> io::Result<PathBuf> { // Made public
         .map(|h| h.join(".config").join("unitodo").join("config.toml"))
 }
 
+// Path of a data file kept next to the primary config (e.g., ~/.config/unitodo/timelog.jsonl)
+pub fn get_data_file_path(file_name: &str) -> io::Result<PathBuf> {
+    get_primary_config_path().map(|p| p.with_file_name(file_name))
+}
+
 // Helper function to find the configuration file path, checking multiple locations
 fn find_config_path() -> Option<PathBuf> { // Stays private to this module
     let primary_path = get_primary_config_path().ok();
@@ -1,4 +1,5 @@
 #![allow(clippy::all)] 
+use crate::todo_models::TodoState;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -46,6 +47,20 @@ pub struct Config {
     pub todo_states: Vec<Vec<String>>,
     #[serde(default = "default_append_basename")]
     pub default_append_basename: String,
+    #[serde(default)]
+    pub auto_complete_parents: bool, // Close a checklist parent when its last open child closes, reopen it with a child
+    #[serde(default)]
+    pub enforce_dependencies: bool, // Refuse to complete a todo while it has open `after:`/`blocks:` prerequisites
+    #[serde(default)]
+    pub views: Vec<ViewConfig>, // Saved queries, in navigation order
+    #[serde(default)]
+    pub category_order: CategoryOrderConfig,
+    #[serde(default)]
+    pub git: GitCategoryConfig,
+    #[serde(default)]
+    pub packages: PackageDiscoveryConfig,
+    #[serde(default)]
+    pub multi_project_membership: bool, // List a todo under every project matching it rather than the first
 }
 
 impl Config {
@@ -65,6 +80,26 @@ impl Config {
         }
         patterns.join("|") // Join all escaped state strings with OR operator
     }
+
+    pub fn effective_todo_states(&self) -> Vec<Vec<String>> {
+        if self.todo_states.is_empty() { default_todo_states() } else { self.todo_states.clone() }
+    }
+
+    pub fn todo_state_of(&self, marker: &str) -> Option<TodoState> {
+        self.effective_todo_states()
+            .iter()
+            .find_map(|state_set| state_set.iter().position(|m| m == marker))
+            .and_then(TodoState::from_index)
+    }
+
+    // State a marker moves to when cycled, mirroring cycle_todo_state (only 4-state sets cycle)
+    pub fn cycled_state_of(&self, marker: &str, forward: bool) -> Option<TodoState> {
+        self.effective_todo_states()
+            .iter()
+            .filter(|state_set| state_set.len() == 4)
+            .find_map(|state_set| state_set.iter().position(|m| m == marker))
+            .and_then(|index| TodoState::from_index(if forward { (index + 1) % 4 } else { (index + 3) % 4 }))
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, Default)]
@@ -81,8 +116,58 @@ pub struct RgConfig {
 pub struct ProjectConfig {
     pub patterns: Vec<String>,
     pub append_file_path: Option<String>,
+    #[serde(default)]
+    pub remotes: Vec<String>, // Globs over normalised remote URLs, e.g. `github.com/acme/*`; any remote of a file's repository counts
+    #[serde(default)]
+    pub priority: i32, // Projects are matched highest priority first, then by name
+    #[serde(default)]
+    pub exclude: Vec<String>, // Paths the project never claims, even when `patterns` or `remotes` match
 }
 
+// Order of categories (and view groups): pinned names first, in the order listed, then the rest sorted by `sort`
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct CategoryOrderConfig {
+    #[serde(default)]
+    pub sort: String, // "" keeps the grouping's own order, "name", "open" (most open todos first) or "count" (most todos first)
+    #[serde(default)]
+    pub pinned: Vec<String>, // Category names, case-insensitive
+}
+
+// How git repositories become categories
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct GitCategoryConfig {
+    #[serde(default)]
+    pub show_branch: bool, // Add the checked-out branch to the name: `api [main]`
+    #[serde(default)]
+    pub show_worktree: bool, // Name linked worktrees after their repository: `api/hotfix` instead of the directory name
+    #[serde(default)]
+    pub group_submodules: bool, // File a submodule's todos under its superproject
+    #[serde(default)]
+    pub group_by_remote: bool, // One category per normalised `origin` URL, so clones of a repository share it
+}
+
+// Sub-project categories found from manifest files inside git repositories
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackageDiscoveryConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub manifests: Vec<String>, // Any of "cargo", "npm", "python", "go"; empty means all of them
+}
+
+// A saved query shown as its own list, e.g. `[[views]] name = "Urgent" query = "state:open prio:<=1"`
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ViewConfig {
+    pub name: String,
+    #[serde(default)]
+    pub query: String, // Query expression (see todo_query); empty matches every todo
+    #[serde(default)]
+    pub sort: Vec<String>, // Sort keys, most significant first; empty keeps the scan order
+    #[serde(default)]
+    pub group_by: String, // A grouping strategy (see grouping); the scan's categories when empty
+    #[serde(default)]
+    pub columns: Option<Vec<String>>, // Columns for clients to show; None leaves the choice to them
+}
 
 pub fn default_search_paths() -> Vec<String> { vec![".".to_string()] } // Made public
 pub fn default_refresh_interval() -> u32 { 5000 } // Made public
@@ -0,0 +1,185 @@
+#![allow(clippy::all)]
+use crate::config_models::Config;
+use crate::todo_models::{DanglingReference, DependencyEdge, DependencyGraph, DependencyNode, ProcessedTodosOutput};
+use crate::todo_parsing::{parse_todo_identity, parse_todo_references, ReferenceKind};
+use crate::todo_processing::find_and_process_todos;
+use crate::utils::extract_cleaned_content_from_line;
+
+use lazy_static::lazy_static;
+use parking_lot::Mutex as ParkingMutex;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+
+// Builds the graph of `after:`/`blocks:` references between scanned todos.
+// Only todos that carry an id or reference another todo become nodes.
+pub fn build_dependency_graph(processed: &ProcessedTodosOutput, config: &Config) -> DependencyGraph {
+    let mut graph = DependencyGraph::default();
+    let mut key_index: HashMap<String, usize> = HashMap::new();
+    let mut references_per_node: Vec<Vec<(ReferenceKind, String)>> = Vec::new();
+
+    for category in &processed.categories {
+        for item in &category.todos {
+            let references: Vec<(ReferenceKind, String)> = parse_todo_references(&item.content)
+                .into_iter()
+                .map(|r| (r.kind, r.target))
+                .collect();
+            if item.id.is_none() && references.is_empty() { continue; }
+            let key = item.id.clone().unwrap_or_else(|| item.location.clone());
+            if key_index.contains_key(&key) { continue; } // The first todo carrying an id wins
+            let open = !config.todo_state_of(&item.status).map_or(false, |s| s.is_closed());
+            key_index.insert(key.clone(), graph.nodes.len());
+            graph.nodes.push(DependencyNode { key, item: item.clone(), category: category.name.clone(), open, blocked_by: Vec::new() });
+            references_per_node.push(references);
+        }
+    }
+
+    let mut seen_edges: HashSet<(usize, usize)> = HashSet::new();
+    let mut adjacency: Vec<Vec<usize>> = vec![Vec::new(); graph.nodes.len()];
+    for (index, references) in references_per_node.iter().enumerate() {
+        for (kind, target) in references {
+            let Some(&target_index) = key_index.get(target) else {
+                graph.dangling.push(DanglingReference { from: graph.nodes[index].key.clone(), reference: target.clone() });
+                continue;
+            };
+            let (from, to) = match kind {
+                ReferenceKind::After => (target_index, index),
+                ReferenceKind::Blocks => (index, target_index),
+            };
+            if seen_edges.insert((from, to)) {
+                adjacency[from].push(to);
+                graph.edges.push(DependencyEdge { from: graph.nodes[from].key.clone(), to: graph.nodes[to].key.clone() });
+            }
+        }
+    }
+
+    for (from, targets) in adjacency.iter().enumerate() {
+        if !graph.nodes[from].open { continue; }
+        let from_key = graph.nodes[from].key.clone();
+        for &to in targets {
+            graph.nodes[to].blocked_by.push(from_key.clone());
+        }
+    }
+    graph.unblocked = graph.nodes.iter().filter(|n| n.open && n.blocked_by.is_empty()).map(|n| n.key.clone()).collect();
+    graph.cycles = find_cycles(&adjacency)
+        .into_iter()
+        .map(|component| component.into_iter().map(|i| graph.nodes[i].key.clone()).collect())
+        .collect();
+    graph
+}
+
+// Strongly connected components (Tarjan) that form a cycle: more than one node, or a node referencing itself
+fn find_cycles(adjacency: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    struct Tarjan<'a> {
+        adjacency: &'a [Vec<usize>],
+        index: Vec<Option<usize>>,
+        lowlink: Vec<usize>,
+        on_stack: Vec<bool>,
+        stack: Vec<usize>,
+        next_index: usize,
+        cycles: Vec<Vec<usize>>,
+    }
+
+    impl<'a> Tarjan<'a> {
+        fn visit(&mut self, v: usize) {
+            self.index[v] = Some(self.next_index);
+            self.lowlink[v] = self.next_index;
+            self.next_index += 1;
+            self.stack.push(v);
+            self.on_stack[v] = true;
+
+            for &w in &self.adjacency[v] {
+                match self.index[w] {
+                    None => {
+                        self.visit(w);
+                        self.lowlink[v] = self.lowlink[v].min(self.lowlink[w]);
+                    }
+                    Some(w_index) if self.on_stack[w] => self.lowlink[v] = self.lowlink[v].min(w_index),
+                    _ => {}
+                }
+            }
+
+            if Some(self.lowlink[v]) == self.index[v] {
+                let mut component = Vec::new();
+                while let Some(w) = self.stack.pop() {
+                    self.on_stack[w] = false;
+                    component.push(w);
+                    if w == v { break; }
+                }
+                if component.len() > 1 || self.adjacency[v].contains(&v) {
+                    component.sort();
+                    self.cycles.push(component);
+                }
+            }
+        }
+    }
+
+    let n = adjacency.len();
+    let mut tarjan = Tarjan { adjacency, index: vec![None; n], lowlink: vec![0; n], on_stack: vec![false; n], stack: Vec::new(), next_index: 0, cycles: Vec::new() };
+    for v in 0..n {
+        if tarjan.index[v].is_none() { tarjan.visit(v); }
+    }
+    tarjan.cycles.sort();
+    tarjan.cycles
+}
+
+// What the last scan knew about todo ids, so one todo's prerequisites can be checked without rescanning
+#[derive(Debug, Default)]
+struct DependencyIndex {
+    search_paths: Vec<String>, // The scanned roots, so another profile's index isn't used
+    open_by_id: HashMap<String, bool>, // Whether the first todo carrying each id was open
+    blockers_by_id: HashMap<String, Vec<(String, bool)>>, // Id -> (key, open) of the todos declaring `blocks:<id>`
+}
+
+lazy_static! {
+    static ref LAST_SCAN_INDEX: ParkingMutex<Option<DependencyIndex>> = ParkingMutex::new(None);
+}
+
+// Keeps the ids and `blocks:` references of a scan for `find_open_prerequisites`
+pub fn remember_scan_dependencies(processed: &ProcessedTodosOutput, config: &Config) {
+    let mut index = DependencyIndex { search_paths: config.rg.paths.clone(), ..Default::default() };
+    for item in processed.categories.iter().flat_map(|c| c.todos.iter()) {
+        let open = !config.todo_state_of(&item.status).map_or(false, |s| s.is_closed());
+        if let Some(id) = &item.id { index.open_by_id.entry(id.clone()).or_insert(open); }
+        for reference in parse_todo_references(&item.content) {
+            if reference.kind != ReferenceKind::Blocks { continue; }
+            let key = item.id.clone().unwrap_or_else(|| item.location.clone());
+            index.blockers_by_id.entry(reference.target).or_default().push((key, open));
+        }
+    }
+    *LAST_SCAN_INDEX.lock() = Some(index);
+}
+
+// The marker and content of the todo at `location` ("file:line"), read from the file itself
+fn read_todo_at(config: &Config, location: &str) -> Option<(String, String)> {
+    let (file_path, line_number) = location.rsplit_once(':')?;
+    let line_number: usize = line_number.parse().ok()?;
+    let text = fs::read_to_string(file_path).ok()?;
+    let line = text.lines().nth(line_number.checked_sub(1)?)?;
+    let pattern = config.get_effective_rg_pattern();
+    let marker = Regex::new(&pattern).ok()?.find(line)?.as_str().to_string();
+    Some((marker, extract_cleaned_content_from_line(line, &pattern).ok()?))
+}
+
+// The open prerequisites of the todo at `location` along with its current marker (None if the location holds no
+// todo). The todo's own line is read from disk; the state of the todos it depends on comes from the last scan, and
+// a scan is only run when there is none yet.
+pub fn find_open_prerequisites(config: &Config, location: &str) -> io::Result<(Option<String>, Vec<String>)> {
+    let Some((marker, content)) = read_todo_at(config, location) else { return Ok((None, Vec::new())) };
+    let has_index = LAST_SCAN_INDEX.lock().as_ref().map_or(false, |index| index.search_paths == config.rg.paths);
+    if !has_index { remember_scan_dependencies(&find_and_process_todos(config, false)?, config); }
+
+    let guard = LAST_SCAN_INDEX.lock();
+    let Some(index) = guard.as_ref() else { return Ok((Some(marker), Vec::new())) };
+    let mut blocked_by: Vec<String> = parse_todo_references(&content).into_iter()
+        .filter(|r| r.kind == ReferenceKind::After && index.open_by_id.get(&r.target) == Some(&true))
+        .map(|r| r.target)
+        .collect();
+    if let Some(id) = parse_todo_identity(&content).id {
+        blocked_by.extend(index.blockers_by_id.get(&id).into_iter().flatten().filter(|(_, open)| *open).map(|(key, _)| key.clone()));
+    }
+    let mut seen = HashSet::new();
+    blocked_by.retain(|key| seen.insert(key.clone()));
+    Ok((Some(marker), blocked_by))
+}
@@ -1,12 +1,186 @@
 #![allow(clippy::all)]
 use crate::config_models::Config;
-use crate::utils::{extract_cleaned_content_from_line, generate_short_timestamp, find_git_repo_root, get_append_file_path_in_dir};
-
+use crate::history::{record_history, FileEvents};
+use crate::todo_models::{HistoryEvent, HistoryEventKind, TodoCategoryEnum, TodoState};
+use crate::natural_dates::{extract_natural_due, NaturalDue};
+use crate::recurrence::{parse_recurrence, next_due_date};
+use crate::todo_parsing::{set_field_in_content, is_valid_field_name, is_valid_field_value, parse_todo_fields, parse_todo_identity, with_todo_id};
+use crate::utils::{extract_cleaned_content_from_line, generate_short_timestamp, generate_nanoid_id, collect_continuation_lines, find_git_repo_root, get_append_file_path_in_dir};
+
+use chrono::Local;
 use std::fs::{self, OpenOptions};
 use std::io::{self, BufReader, Read, Write, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
 use regex::Regex;
 use fs2::FileExt;
+use std::fmt;
+
+// A todo's line no longer holds the content the caller last read, so the operation was refused
+#[derive(Debug)]
+pub struct ContentModified {
+    expected: String,
+    found: String,
+}
+
+impl fmt::Display for ContentModified {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Content modified. Expected: '{}', Found on disk: '{}'", self.expected, self.found)
+    }
+}
+
+impl std::error::Error for ContentModified {}
+
+fn content_modified(expected: &str, found: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, ContentModified { expected: expected.trim().to_string(), found: found.trim().to_string() })
+}
+
+// --- Line-level helpers shared by the file operations ---
+
+// Rewrites the marker on `line` to the `target` state of the marker's own 4-state set, appending a `@@` done timestamp
+// to the first word when closing and dropping it when reopening. Returns None if the marker isn't in a 4-state set.
+#[rustfmt::skip]
+pub fn rewrite_line_state(line: &str, marker_re: &Regex, todo_state_sets: &[Vec<String>], target: TodoState) -> Option<String> {
+    let mat = marker_re.find(line)?;
+    let current_marker = mat.as_str();
+    let state_set = todo_state_sets.iter().find(|set| set.len() == 4 && set.iter().any(|m| m == current_marker))?;
+    let current_state = TodoState::from_index(state_set.iter().position(|m| m == current_marker)?)?;
+    if current_state == target { return Some(line.to_string()); }
+
+    let after_marker = &line[mat.end()..];
+    let content = after_marker.trim_start();
+    let spacing = &after_marker[..after_marker.len() - content.len()];
+    let done_ts_regex = Regex::new(r"@@[A-Za-z0-9\-_]{5}").unwrap();
+    let new_content = if target.is_closed() {
+        let (first_word, rest) = match content.split_once(' ') { Some((f, r)) => (f, Some(r)), None => (content, None) };
+        let done_ts_str = format!("@@{}", generate_short_timestamp());
+        let first_word = if done_ts_regex.is_match(first_word) { done_ts_regex.replace(first_word, done_ts_str.as_str()).into_owned() } else { format!("{}{}", first_word, done_ts_str) };
+        match rest { Some(r) => format!("{} {}", first_word, r), None => first_word }
+    } else if current_state.is_closed() {
+        done_ts_regex.replace_all(content, "").trim_start().to_string()
+    } else {
+        content.to_string()
+    };
+    Some(format!("{}{}{}{}", &line[..mat.start()], state_set[target.index()], spacing, new_content))
+}
+
+// Indentation of a checklist todo (a marker preceded only by whitespace), None for other lines
+#[rustfmt::skip]
+fn checklist_indent(line: &str, marker_re: &Regex) -> Option<usize> {
+    let mat = marker_re.find(line)?;
+    let prefix = &line[..mat.start()];
+    if prefix.trim().is_empty() { Some(prefix.len()) } else { None }
+}
+
+// Finds the checklist todo that the todo on `child_index` is nested under, using the scanner's indentation rules
+#[rustfmt::skip]
+pub fn find_parent_todo_line(lines: &[String], child_index: usize, marker_re: &Regex) -> Option<usize> {
+    let child_indent = checklist_indent(&lines[child_index], marker_re)?;
+    for i in (0..child_index).rev() {
+        let line = &lines[i];
+        if line.trim().is_empty() { continue; }
+        let indent = line.len() - line.trim_start().len();
+        if indent >= child_indent { continue; }
+        return checklist_indent(line, marker_re).map(|_| i);
+    }
+    None
+}
+
+#[rustfmt::skip]
+fn direct_child_todo_lines(lines: &[String], parent_index: usize, marker_re: &Regex) -> Vec<usize> {
+    let parent_indent = match checklist_indent(&lines[parent_index], marker_re) { Some(indent) => indent, None => return Vec::new() };
+    let mut children = Vec::new();
+    for i in parent_index + 1..lines.len() {
+        let line = &lines[i];
+        if line.trim().is_empty() { continue; }
+        if line.len() - line.trim_start().len() <= parent_indent { break; }
+        if checklist_indent(line, marker_re).is_some() && find_parent_todo_line(lines, i, marker_re) == Some(parent_index) {
+            children.push(i);
+        }
+    }
+    children
+}
+
+#[rustfmt::skip]
+fn line_state(line: &str, marker_re: &Regex, config: &Config) -> Option<TodoState> {
+    marker_re.find(line).and_then(|mat| config.todo_state_of(mat.as_str()))
+}
+
+// After the todo on `child_index` changed state: closes its parent once every direct child is closed, or reopens a
+// closed parent when the child was reopened, walking up the tree. Returns the 0-based indices of rewritten lines.
+#[rustfmt::skip]
+pub fn propagate_state_to_parents(lines: &mut Vec<String>, child_index: usize, marker_re: &Regex, config: &Config) -> Vec<usize> {
+    let todo_state_sets = config.effective_todo_states();
+    let mut updated = Vec::new();
+    let mut current = child_index;
+    while let Some(parent) = find_parent_todo_line(lines, current, marker_re) {
+        let (child_state, parent_state) = match (line_state(&lines[current], marker_re, config), line_state(&lines[parent], marker_re, config)) {
+            (Some(c), Some(p)) => (c, p),
+            _ => break,
+        };
+        let target = if child_state.is_closed() {
+            let all_children_closed = direct_child_todo_lines(lines, parent, marker_re).iter()
+                .all(|&c| line_state(&lines[c], marker_re, config).map_or(false, |s| s.is_closed()));
+            if all_children_closed && !parent_state.is_closed() { TodoState::Done } else { break }
+        } else if parent_state.is_closed() {
+            TodoState::Open
+        } else {
+            break
+        };
+        match rewrite_line_state(&lines[parent], marker_re, &todo_state_sets, target) {
+            Some(new_line) => { lines[parent] = new_line; updated.push(parent); current = parent; }
+            None => break,
+        }
+    }
+    updated
+}
+
+// After the recurring todo on `done_index` was completed from `open_line`, inserts its next instance below it (after its
+// continuation lines and nested items): open again, with a new id, the next `@due` and `@prev` pointing back. A completed
+// todo without an id is given one so it can be linked. Returns the inserted index and the new instance's content.
+#[rustfmt::skip]
+fn spawn_recurring_copy(lines: &mut Vec<String>, done_index: usize, open_line: &str, marker_re: &Regex) -> Option<(usize, String)> {
+    let mat = marker_re.find(open_line)?;
+    let content_start = open_line[mat.end()..].find(|c: char| !c.is_whitespace()).map_or(open_line.len(), |i| mat.end() + i);
+    let content = open_line[content_start..].trim();
+    let fields = parse_todo_fields(content);
+    let recurrence = parse_recurrence(&fields.get("every")?.raw)?;
+    let next_due = next_due_date(&recurrence, fields.get("due").map(|f| f.raw.as_str()), &Local::now().naive_local())?;
+
+    let previous_id = match parse_todo_identity(content).id {
+        Some(id) => id,
+        None => {
+            let id = generate_nanoid_id();
+            let done_line = lines[done_index].clone();
+            let done_mat = marker_re.find(&done_line)?;
+            let done_start = done_line[done_mat.end()..].find(|c: char| !c.is_whitespace()).map_or(done_line.len(), |i| done_mat.end() + i);
+            lines[done_index] = format!("{}{}", &done_line[..done_start], with_todo_id(done_line[done_start..].trim(), &id));
+            id
+        }
+    };
+    let new_id = if previous_id.starts_with('@') && !previous_id.starts_with("@@") { format!("@{}", generate_short_timestamp()) } else { generate_nanoid_id() };
+    let mut new_content = with_todo_id(content, &new_id);
+    new_content = set_field_in_content(&new_content, "due", Some(&next_due));
+    new_content = set_field_in_content(&new_content, "prev", Some(&previous_id));
+    new_content = set_field_in_content(&new_content, "finished", None);
+
+    let following = lines[done_index + 1..].iter().map(|l| l.as_str());
+    let mut insert_at = done_index + 1 + collect_continuation_lines(&lines[done_index], mat.start(), following, marker_re).len();
+    if let Some(indent) = checklist_indent(&lines[done_index], marker_re) {
+        while insert_at < lines.len() && !lines[insert_at].trim().is_empty() && lines[insert_at].len() - lines[insert_at].trim_start().len() > indent { insert_at += 1; }
+    }
+    let separator = if open_line[..content_start].ends_with(char::is_whitespace) { "" } else { " " };
+    lines.insert(insert_at, format!("{}{}{}", &open_line[..content_start], separator, new_content));
+    Some((insert_at, new_content))
+}
+
+// History events for a todo whose marker changed from `previous_marker`, and for the parents that followed it
+fn state_change_events(file_events: &FileEvents, file_path_str: &str, lines: &[String], line_index: usize, previous_marker: &str, parent_indices: &[usize]) -> Vec<HistoryEvent> {
+    let event = |index: usize, previous: Option<String>| file_events.state_event(&format!("{}:{}", file_path_str, index + 1), &lines[index], previous);
+    std::iter::once(event(line_index, Some(previous_marker.to_string())))
+        .chain(parent_indices.iter().map(|&index| event(index, None)))
+        .flatten()
+        .collect()
+}
 
 // --- Core File Operation Logic (uses active_profile_config) ---
 #[rustfmt::skip]
@@ -19,7 +193,10 @@ pub fn edit_todo_in_file_grpc(active_profile_config: &Config, location: &str, ne
     let line_index = line_number - 1;
     let file_path = Path::new(file_path_str);
     if !file_path.is_file() { return Err(io::Error::new(io::ErrorKind::NotFound, "File not found")); }
+    // Only the head line of a todo is rewritten; writing line breaks into it would split the item and orphan its continuation lines
+    if new_content.trim().contains(|c: char| c == '\n' || c == '\r') { return Err(io::Error::new(io::ErrorKind::InvalidInput, "Multi-line content cannot be written by edit; edit continuation lines in the file directly")); }
 
+    let file_events = FileEvents::new(active_profile_config, file_path_str);
     let mut file = OpenOptions::new().read(true).write(true).open(file_path)?;
     file.lock_exclusive()?;
     let result = (|| {
@@ -54,6 +231,7 @@ pub fn edit_todo_in_file_grpc(active_profile_config: &Config, location: &str, ne
             file.set_len(0)?;
             file.seek(SeekFrom::Start(0))?;
             file.write_all(final_write_content.as_bytes())?;
+            record_history(file_events.event(HistoryEventKind::Edited, location, &lines[line_index], Some(original_content.trim().to_string())).into_iter().collect());
             Ok(())
         } else {
             Err(io::Error::new(io::ErrorKind::NotFound, "TODO pattern not found on line for edit")) // UNITODO_IGNORE_LINE
@@ -63,20 +241,144 @@ pub fn edit_todo_in_file_grpc(active_profile_config: &Config, location: &str, ne
     result
 }
 
+// Applies `@name(value)` field updates (None removes the field) to a todo's content in place. Returns the new content.
 #[rustfmt::skip]
-pub fn add_todo_to_file_grpc(active_profile_config: &Config, category_type: &str, category_name: &str, content: &str, example_item_location: Option<&str>) -> io::Result<()> {
-    let target_append_file_path: PathBuf = match category_type {
-        "git" => {
-            let ex_loc = example_item_location.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Git add requires example_item_location"))?;
-            let ex_path_str = ex_loc.split(':').next().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid example_item_location format for git add"))?;
+pub fn update_todo_fields_in_file_grpc(active_profile_config: &Config, location: &str, original_content: &str, updates: &[(String, Option<String>)]) -> io::Result<String> {
+    let location_parts: Vec<&str> = location.splitn(2, ':').collect();
+    if location_parts.len() != 2 { return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid location format for update_todo_fields")); }
+    let file_path_str = location_parts[0];
+    let line_number: usize = location_parts[1].parse().map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Invalid line number for update_todo_fields"))?;
+    if line_number == 0 { return Err(io::Error::new(io::ErrorKind::InvalidInput, "Line number cannot be 0 for update_todo_fields")); }
+    let line_index = line_number - 1;
+    let file_path = Path::new(file_path_str);
+    if !file_path.is_file() { return Err(io::Error::new(io::ErrorKind::NotFound, "File not found for update_todo_fields")); }
+    for (name, value) in updates {
+        if !is_valid_field_name(name) { return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid field name '{}'", name))); }
+        if let Some(v) = value { if !is_valid_field_value(v) { return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid value for field '{}': must be non-empty, on one line and without parentheses", name))); } }
+    }
+
+    let file_events = FileEvents::new(active_profile_config, file_path_str);
+    let mut file = OpenOptions::new().read(true).write(true).open(file_path)?;
+    file.lock_exclusive()?;
+    let result = (|| {
+        let mut file_content_string = String::new();
+        BufReader::new(&file).read_to_string(&mut file_content_string)?;
+        let mut lines: Vec<String> = file_content_string.lines().map(String::from).collect();
+        if line_index >= lines.len() { return Err(io::Error::new(io::ErrorKind::InvalidInput, "Line number out of bounds for update_todo_fields")); }
+
+        let original_line = &lines[line_index];
+        let todo_pattern_re = Regex::new(&active_profile_config.get_effective_rg_pattern()).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Bad regex for update_todo_fields pattern"))?;
+        let mat = todo_pattern_re.find(original_line).ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "TODO pattern not found on line for update_todo_fields"))?; // UNITODO_IGNORE_LINE
+        let content_start_idx = original_line[mat.end()..].find(|c: char| !c.is_whitespace()).map_or(original_line.len(), |i| mat.end() + i);
+        let current_on_disk_content = original_line[content_start_idx..].trim();
+        if current_on_disk_content != original_content.trim() {
+            return Err(content_modified(original_content, current_on_disk_content));
+        }
+
+        let new_content = updates.iter().fold(current_on_disk_content.to_string(), |content, (name, value)| set_field_in_content(&content, name, value.as_deref()));
+        let prefix = &original_line[..content_start_idx];
+        let separator = if prefix.ends_with(char::is_whitespace) || new_content.is_empty() { "" } else { " " };
+        lines[line_index] = format!("{}{}{}", prefix, separator, new_content);
+
+        let new_full_content = lines.join("\n");
+        let final_write_content = if file_content_string.ends_with('\n') && !new_full_content.is_empty() { format!("{}\n", new_full_content) } else { new_full_content };
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(final_write_content.as_bytes())?;
+        record_history(file_events.event(HistoryEventKind::FieldsChanged, location, &lines[line_index], Some(original_content.trim().to_string())).into_iter().collect());
+        Ok(new_content)
+    })();
+    fs2::FileExt::unlock(&file)?;
+    result
+}
+
+// Moves a todo to `target` within its 4-state marker set. Returns the new content, the new marker and any parents updated.
+#[rustfmt::skip]
+pub fn set_todo_state_in_file_grpc(active_profile_config: &Config, location: &str, original_content: &str, target: TodoState) -> io::Result<(String, String, Vec<String>)> {
+    let location_parts: Vec<&str> = location.splitn(2, ':').collect();
+    if location_parts.len() != 2 { return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid location format for set_todo_state")); }
+    let file_path_str = location_parts[0];
+    let line_number: usize = location_parts[1].parse().map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Invalid line number for set_todo_state"))?;
+    if line_number == 0 { return Err(io::Error::new(io::ErrorKind::InvalidInput, "Line number cannot be 0 for set_todo_state")); }
+    let line_index = line_number - 1;
+    let file_path = Path::new(file_path_str);
+    if !file_path.is_file() { return Err(io::Error::new(io::ErrorKind::NotFound, "File not found for set_todo_state")); }
+
+    let file_events = FileEvents::new(active_profile_config, file_path_str);
+    let mut file = OpenOptions::new().read(true).write(true).open(file_path)?;
+    file.lock_exclusive()?;
+    let result = (|| {
+        let mut file_content_string = String::new();
+        BufReader::new(&file).read_to_string(&mut file_content_string)?;
+        let mut lines: Vec<String> = file_content_string.lines().map(String::from).collect();
+        if line_index >= lines.len() { return Err(io::Error::new(io::ErrorKind::InvalidInput, "Line number out of bounds for set_todo_state")); }
+
+        let effective_rg_pattern = active_profile_config.get_effective_rg_pattern();
+        let current_on_disk_content = extract_cleaned_content_from_line(&lines[line_index], &effective_rg_pattern)?;
+        if current_on_disk_content.trim() != original_content.trim() {
+            return Err(content_modified(original_content, &current_on_disk_content));
+        }
+
+        let marker_re = Regex::new(&effective_rg_pattern).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Bad regex for set_todo_state marker pattern"))?;
+        let new_line = rewrite_line_state(&lines[line_index], &marker_re, &active_profile_config.effective_todo_states(), target)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Current marker not found in any configured 4-state cycle for set_todo_state"))?;
+        let previous_marker = marker_re.find(&lines[line_index]).map(|m| m.as_str().to_string()).unwrap_or_default();
+        let new_marker = marker_re.find(&new_line).map(|m| m.as_str().to_string()).unwrap_or_default();
+        let new_content = extract_cleaned_content_from_line(&new_line, &effective_rg_pattern)?.trim().to_string();
+        lines[line_index] = new_line;
+        let parent_indices = if active_profile_config.auto_complete_parents { propagate_state_to_parents(&mut lines, line_index, &marker_re, active_profile_config) } else { Vec::new() };
+        let updated_parents: Vec<String> = parent_indices.iter().map(|i| format!("{}:{}", file_path_str, i + 1)).collect();
+
+        let new_full_content = lines.join("\n");
+        let final_write_content = if file_content_string.ends_with('\n') && !new_full_content.is_empty() { format!("{}\n", new_full_content) } else { new_full_content };
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(final_write_content.as_bytes())?;
+        if new_marker != previous_marker { record_history(state_change_events(&file_events, file_path_str, &lines, line_index, &previous_marker, &parent_indices)); }
+        Ok((new_content, new_marker, updated_parents))
+    })();
+    fs2::FileExt::unlock(&file)?;
+    result
+}
+
+// The category is `category_key` (a TodoCategory key) when given; otherwise a project by name, or the git repository
+// holding `example_item_location`. Unless `parse_dates` is off, a date phrase at the end of the content ("due fri",
+// "tomorrow 3pm") becomes a `@due(...)` field; the interpretation is returned so it can be confirmed.
+#[rustfmt::skip]
+pub fn add_todo_to_file_grpc(active_profile_config: &Config, category_key: Option<&str>, category_type: &str, category_name: &str, content: &str, example_item_location: Option<&str>, parse_dates: bool) -> io::Result<Option<NaturalDue>> {
+    let category = match category_key {
+        Some(key) => TodoCategoryEnum::from_key(key).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid category key '{}'", key)))?,
+        None => match category_type {
+            "git" => {
+                let ex_loc = example_item_location.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Git add requires category_key or example_item_location"))?;
+                let ex_path_str = ex_loc.split(':').next().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid example_item_location format for git add"))?;
+                let repo_root = find_git_repo_root(Path::new(ex_path_str))?.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Could not find git repo root for add"))?;
+                TodoCategoryEnum::GitRepo(repo_root.to_string_lossy().into_owned())
+            }
+            "project" => TodoCategoryEnum::Project(category_name.to_string()),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid category_type for add_todo"))
+        },
+    };
+    let target_append_file_path: PathBuf = match &category {
+        TodoCategoryEnum::GitRepo(root) => {
+            if !Path::new(root).is_dir() { return Err(io::Error::new(io::ErrorKind::NotFound, format!("Git repository '{}' not found", root))); }
+            get_append_file_path_in_dir(Path::new(root), &active_profile_config.default_append_basename)
+        }
+        TodoCategoryEnum::Project(name) => {
+            let proj_conf = active_profile_config.projects.get(name).ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("Project config not found for '{}'", name)))?;
+            PathBuf::from(proj_conf.append_file_path.as_ref().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("append_file_path not configured for project '{}'", name)))?)
+        }
+        TodoCategoryEnum::Package(dir) => {
+            if !Path::new(dir).is_dir() { return Err(io::Error::new(io::ErrorKind::NotFound, format!("Package directory '{}' not found", dir))); }
+            get_append_file_path_in_dir(Path::new(dir), &active_profile_config.default_append_basename)
+        }
+        TodoCategoryEnum::Remote(url) => { // Several clones may share the category; the example picks one
+            let ex_loc = example_item_location.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("Adding to remote '{}' requires example_item_location to pick a clone", url)))?;
+            let ex_path_str = ex_loc.split(':').next().unwrap_or(ex_loc);
             let repo_root = find_git_repo_root(Path::new(ex_path_str))?.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Could not find git repo root for add"))?;
             get_append_file_path_in_dir(&repo_root, &active_profile_config.default_append_basename)
         }
-        "project" => {
-            let proj_conf = active_profile_config.projects.get(category_name).ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("Project config not found for '{}'", category_name)))?;
-            PathBuf::from(proj_conf.append_file_path.as_ref().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("append_file_path not configured for project '{}'", category_name)))?)
-        }
-        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid category_type for add_todo"))
+        TodoCategoryEnum::Other => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Cannot add todos to the Other category")),
     };
 
     let timestamp_str = generate_short_timestamp();
@@ -99,28 +401,44 @@ pub fn add_todo_to_file_grpc(active_profile_config: &Config, category_type: &str
         }
     };
 
+    let natural_due = if parse_dates && !parse_todo_fields(&content_segment).contains_key("due") { extract_natural_due(&content_segment, &Local::now().naive_local()) } else { None };
+    let content_segment = match &natural_due { Some(parsed) => set_field_in_content(&parsed.remaining, "due", Some(&parsed.due)), None => content_segment };
+
     let base_line_to_append = format!("{}{}{}@{} {}", todo_marker, effective_priority_segment, "", timestamp_str, content_segment).trim_end().to_string(); // Removed space before @, already in effective_priority_segment or content_segment if needed
 
+    let file_events = FileEvents::new(active_profile_config, &target_append_file_path.to_string_lossy());
+    let appended_line_number = append_todo_line(&target_append_file_path, &base_line_to_append)?;
+    let appended_location = format!("{}:{}", target_append_file_path.display(), appended_line_number);
+    record_history(file_events.event(HistoryEventKind::Created, &appended_location, &base_line_to_append, None).into_iter().collect());
+    Ok(natural_due)
+}
+
+// Appends `line` to a file (created if missing), after a newline if the file doesn't end with one. Returns its 1-based line number.
+#[rustfmt::skip]
+pub fn append_todo_line(target_append_file_path: &Path, line: &str) -> io::Result<usize> {
     if let Some(parent_dir) = target_append_file_path.parent() { fs::create_dir_all(parent_dir)?; }
     else { return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid target append file path (no parent dir)")); }
 
-    let mut file = OpenOptions::new().read(true).write(true).append(true).create(true).open(&target_append_file_path)?;
+    let mut file = OpenOptions::new().read(true).write(true).append(true).create(true).open(target_append_file_path)?;
     file.lock_exclusive()?;
-    let mut needs_newline = false;
-    if file.metadata()?.len() > 0 {
-        file.seek(SeekFrom::End(-1))?;
-        let mut last_char_buf = [0;1];
-        file.read_exact(&mut last_char_buf)?;
-        if last_char_buf[0] != b'\n' { needs_newline = true; }
-    }
-    let final_append_str = if needs_newline { format!("\n{}", base_line_to_append) } else { base_line_to_append };
-    writeln!(file, "{}", final_append_str)?;
+    let result = (|| {
+        let mut needs_newline = false;
+        if file.metadata()?.len() > 0 {
+            file.seek(SeekFrom::End(-1))?;
+            let mut last_char_buf = [0;1];
+            file.read_exact(&mut last_char_buf)?;
+            if last_char_buf[0] != b'\n' { needs_newline = true; }
+        }
+        let final_append_str = if needs_newline { format!("\n{}", line) } else { line.to_string() };
+        writeln!(file, "{}", final_append_str)?;
+        Ok(fs::read_to_string(target_append_file_path)?.lines().count())
+    })();
     fs2::FileExt::unlock(&file)?;
-    Ok(())
+    result
 }
 
 #[rustfmt::skip]
-pub fn cycle_todo_state_in_file_grpc(active_profile_config: &Config, location: &str, original_content_payload: &str, direction: i32) -> Result<(String, String), io::Error> {
+pub fn cycle_todo_state_in_file_grpc(active_profile_config: &Config, location: &str, original_content_payload: &str, direction: i32) -> Result<(String, String, Vec<String>), io::Error> {
     let location_parts: Vec<&str> = location.splitn(2, ':').collect();
     if location_parts.len() != 2 { return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid location format for cycle_todo_state")); }
     let file_path_str = location_parts[0];
@@ -130,10 +448,11 @@ pub fn cycle_todo_state_in_file_grpc(active_profile_config: &Config, location: &
     let file_path = Path::new(file_path_str);
     if !file_path.is_file() { return Err(io::Error::new(io::ErrorKind::NotFound, "File not found for cycle_todo_state")); }
 
+    let file_events = FileEvents::new(active_profile_config, file_path_str);
     let mut file = OpenOptions::new().read(true).write(true).open(file_path)?;
     file.lock_exclusive()?;
 
-    let result: Result<(String, String), io::Error> = (|| {
+    let result: Result<(String, String, Vec<String>), io::Error> = (|| {
         let mut original_file_content_string = String::new();
         BufReader::new(&file).read_to_string(&mut original_file_content_string)?;
         let mut lines: Vec<String> = original_file_content_string.lines().map(String::from).collect();
@@ -144,8 +463,7 @@ pub fn cycle_todo_state_in_file_grpc(active_profile_config: &Config, location: &
         let current_disk_cleaned_content = extract_cleaned_content_from_line(&original_line_on_disk, &effective_rg_pattern)?;
         
         if current_disk_cleaned_content.trim() != original_content_payload.trim() { 
-            let error_msg = format!("Content modified. Expected: '{}', Found on disk: '{}'", original_content_payload.trim(), current_disk_cleaned_content.trim());
-            return Err(io::Error::new(io::ErrorKind::Other, error_msg)); 
+            return Err(content_modified(original_content_payload, &current_disk_cleaned_content));
         }
 
         let todo_state_sets = if active_profile_config.todo_states.is_empty() { 
@@ -254,13 +572,17 @@ pub fn cycle_todo_state_in_file_grpc(active_profile_config: &Config, location: &
         }
 
         lines[line_index] = final_line_to_write;
+        let parent_indices = if active_profile_config.auto_complete_parents { propagate_state_to_parents(&mut lines, line_index, &marker_re, active_profile_config) } else { Vec::new() };
+        let updated_parents: Vec<String> = parent_indices.iter().map(|i| format!("{}:{}", file_path_str, i + 1)).collect();
         let new_full_content = lines.join("\n");
         let final_write_content = if original_file_content_string.ends_with('\n') && !new_full_content.is_empty() { format!("{}\n", new_full_content) } else { new_full_content };
         file.set_len(0)?;
         file.seek(SeekFrom::Start(0))?;
         file.write_all(final_write_content.as_bytes())?;
+        let previous_marker = marker_re.find(&original_line_on_disk).map_or("", |m| m.as_str());
+        record_history(state_change_events(&file_events, file_path_str, &lines, line_index, previous_marker, &parent_indices));
         
-        Ok((final_content_for_frontend, new_marker_for_response))
+        Ok((final_content_for_frontend, new_marker_for_response, updated_parents))
     })();
 
     fs2::FileExt::unlock(&file)?;
@@ -268,7 +590,7 @@ pub fn cycle_todo_state_in_file_grpc(active_profile_config: &Config, location: &
 }
 
 #[rustfmt::skip]
-pub fn mark_todo_as_done_in_file_grpc(active_profile_config: &Config, location: &str, original_content_payload: &str) -> Result<(String, bool), io::Error> {
+pub fn mark_todo_as_done_in_file_grpc(active_profile_config: &Config, location: &str, original_content_payload: &str) -> Result<(String, bool, Vec<String>, Option<(String, String)>), io::Error> {
     let location_parts: Vec<&str> = location.splitn(2, ':').collect();
     if location_parts.len() != 2 { return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid location format for mark_done")); }
     let file_path_str = location_parts[0];
@@ -278,9 +600,10 @@ pub fn mark_todo_as_done_in_file_grpc(active_profile_config: &Config, location:
     let file_path = Path::new(file_path_str);
     if !file_path.is_file() { return Err(io::Error::new(io::ErrorKind::NotFound, "File not found for mark_done")); }
 
+    let file_events = FileEvents::new(active_profile_config, file_path_str);
     let mut file = OpenOptions::new().read(true).write(true).open(file_path)?;
     file.lock_exclusive()?;
-    let result: Result<(String, bool), io::Error> = (|| {
+    let result: Result<(String, bool, Vec<String>, Option<(String, String)>), io::Error> = (|| {
         let mut original_file_content_string = String::new();
         BufReader::new(&file).read_to_string(&mut original_file_content_string)?;
         let mut lines: Vec<String> = original_file_content_string.lines().map(String::from).collect();
@@ -291,8 +614,7 @@ pub fn mark_todo_as_done_in_file_grpc(active_profile_config: &Config, location:
         let current_disk_cleaned_content = extract_cleaned_content_from_line(&original_line_on_disk, &effective_rg_pattern)?;
         
         if current_disk_cleaned_content.trim() != original_content_payload.trim() { 
-            let error_msg = format!("Content modified. Expected: '{}', Found on disk: '{}'", original_content_payload.trim(), current_disk_cleaned_content.trim());
-            return Err(io::Error::new(io::ErrorKind::Other, error_msg)); 
+            return Err(content_modified(original_content_payload, &current_disk_cleaned_content));
         }
 
         let todo_state_sets = if active_profile_config.todo_states.is_empty() { 
@@ -342,13 +664,28 @@ pub fn mark_todo_as_done_in_file_grpc(active_profile_config: &Config, location:
         } else { return Err(io::Error::new(io::ErrorKind::NotFound, "TODO pattern not found on line for mark_done")); } // UNITODO_IGNORE_LINE
 
         lines[line_index] = final_line_to_write;
+        // Spawned before parents are updated, so a parent isn't closed while the next instance is open under it
+        let spawned = if marker_transformed { spawn_recurring_copy(&mut lines, line_index, &original_line_on_disk, &marker_re) } else { None };
+        let final_content_for_frontend = match (&spawned, marker_re.find(&lines[line_index])) { (Some(_), Some(m)) => lines[line_index][m.end()..].trim_start().to_string(), _ => final_content_for_frontend }; // The id may have been added
+        let spawned_index = spawned.as_ref().map(|(index, _)| *index);
+        let spawned = spawned.map(|(index, content)| (format!("{}:{}", file_path_str, index + 1), content));
+        let parent_indices = if active_profile_config.auto_complete_parents && marker_transformed { propagate_state_to_parents(&mut lines, line_index, &marker_re, active_profile_config) } else { Vec::new() };
+        let updated_parents: Vec<String> = parent_indices.iter().map(|i| format!("{}:{}", file_path_str, i + 1)).collect();
         let new_full_content = lines.join("\n");
         let final_write_content = if original_file_content_string.ends_with('\n') && !new_full_content.is_empty() { format!("{}\n", new_full_content) } else { new_full_content };
         file.set_len(0)?;
         file.seek(SeekFrom::Start(0))?;
         file.write_all(final_write_content.as_bytes())?;
+        if marker_transformed {
+            let previous_marker = marker_re.find(&original_line_on_disk).map_or("", |m| m.as_str());
+            let mut events = state_change_events(&file_events, file_path_str, &lines, line_index, previous_marker, &parent_indices);
+            if let Some(index) = spawned_index {
+                events.extend(file_events.event(HistoryEventKind::Created, &format!("{}:{}", file_path_str, index + 1), &lines[index], None));
+            }
+            record_history(events);
+        }
         
-        Ok((final_content_for_frontend, marker_transformed))
+        Ok((final_content_for_frontend, marker_transformed, updated_parents, spawned))
     })();
     fs2::FileExt::unlock(&file)?;
     result
@@ -0,0 +1,115 @@
+#![allow(clippy::all)]
+use crate::todo_parsing::{is_priority_like, parse_todo_identity, strip_todo_fields};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+
+// --- Fingerprints: stable keys for todos without an ID ---
+// A fingerprint hashes a todo's file and its normalised text, so it survives line shifts and changes of priority, id,
+// fields and state. Identical todos in one file are told apart by their neighbouring lines. Scans match todos whose
+// fingerprints differ by text similarity and carry the earlier fingerprint forward (see scan_changes).
+
+// Similarity a todo's text must keep to be matched after an edit, within its file and after moving to another file
+pub const SAME_FILE_SIMILARITY: f64 = 0.75;
+pub const OTHER_FILE_SIMILARITY: f64 = 0.9;
+
+lazy_static! {
+    static ref DONE_STAMP_RE: Regex = Regex::new(r"@@[A-Za-z0-9\-_]{5}").unwrap();
+}
+
+// Text of a todo without its priority/id word, fields and done stamps, lowercased with whitespace collapsed
+pub fn normalise_todo_content(content: &str) -> String {
+    let content = content.trim();
+    let identity = parse_todo_identity(content);
+    let has_identity_word = identity.id.is_some() || identity.done_stamp.is_some() || identity.priority.as_deref().map_or(false, is_priority_like);
+    let body = if has_identity_word { content.split_once(char::is_whitespace).map_or("", |(_, rest)| rest) } else { content };
+    let body = strip_todo_fields(&DONE_STAMP_RE.replace_all(body, " "));
+    let body = body.trim_end().trim_end_matches("*/").trim_end_matches("-->");
+    body.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+// FNV-1a, so fingerprints stay the same across builds and platforms
+fn fnv1a_64(parts: &[&str]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for (i, part) in parts.iter().enumerate() {
+        let separator: &[u8] = if i == 0 { &[] } else { &[0] };
+        for byte in separator.iter().chain(part.as_bytes()) {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    format!("{:016x}", hash)
+}
+
+// Fingerprint of a todo from its file and text alone
+pub fn content_fingerprint(file: &str, content: &str) -> String {
+    fnv1a_64(&[file, &normalise_todo_content(content)])
+}
+
+// A todo in one file, in line order, with the lines around it
+pub struct FingerprintInput<'a> {
+    pub content: &'a str,
+    pub previous_line: Option<&'a str>,
+    pub next_line: Option<&'a str>,
+}
+
+// Fingerprints of the todos in `file`. The first todo with a given text gets its content fingerprint; later identical
+// ones mix in their neighbouring lines, then their occurrence number if those don't tell them apart either.
+pub fn fingerprint_file_todos(file: &str, todos: &[FingerprintInput]) -> Vec<String> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut taken: HashSet<String> = HashSet::new();
+    todos.iter().map(|todo| {
+        let normalised = normalise_todo_content(todo.content);
+        let occurrence = seen.entry(normalised.clone()).or_insert(0);
+        *occurrence += 1;
+        let mut fingerprint = fnv1a_64(&[file, &normalised]);
+        if *occurrence > 1 {
+            let neighbour = |line: Option<&str>| line.map_or(String::new(), |l| l.split_whitespace().collect::<Vec<_>>().join(" "));
+            fingerprint = fnv1a_64(&[file, &normalised, &neighbour(todo.previous_line), &neighbour(todo.next_line)]);
+        }
+        let base = fingerprint.clone();
+        let mut n = 1;
+        while !taken.insert(fingerprint.clone()) {
+            n += 1;
+            fingerprint = fnv1a_64(&[&base, &n.to_string()]);
+        }
+        fingerprint
+    }).collect()
+}
+
+// A todo's normalised text with its character bigrams, counted once so it can be compared with many others
+pub struct TodoText {
+    pub normalised: String,
+    bigrams: HashMap<(char, char), usize>,
+    bigram_count: usize,
+}
+
+impl TodoText {
+    pub fn new(content: &str) -> Self {
+        let normalised = normalise_todo_content(content);
+        let chars: Vec<char> = normalised.chars().collect();
+        let mut bigrams: HashMap<(char, char), usize> = HashMap::new();
+        for pair in chars.windows(2) { *bigrams.entry((pair[0], pair[1])).or_insert(0) += 1; }
+        let bigram_count = chars.len().saturating_sub(1);
+        TodoText { normalised, bigrams, bigram_count }
+    }
+
+    // Whether the similarity can reach `threshold` at all, from the bigram counts alone: shared bigrams can't outnumber
+    // the shorter text's
+    pub fn could_reach(&self, other: &TodoText, threshold: f64) -> bool {
+        if self.normalised == other.normalised { return true; }
+        let total = self.bigram_count + other.bigram_count;
+        total > 0 && 2.0 * self.bigram_count.min(other.bigram_count) as f64 / total as f64 >= threshold
+    }
+
+    // Similarity of two todo texts in [0, 1]: the Dice coefficient of their character bigrams
+    pub fn similarity(&self, other: &TodoText) -> f64 {
+        if self.normalised == other.normalised { return 1.0; }
+        let total = self.bigram_count + other.bigram_count;
+        if total == 0 { return 0.0; }
+        let (smaller, larger) = if self.bigrams.len() <= other.bigrams.len() { (self, other) } else { (other, self) };
+        let shared: usize = smaller.bigrams.iter().map(|(pair, count)| (*count).min(*larger.bigrams.get(pair).unwrap_or(&0))).sum();
+        2.0 * shared as f64 / total as f64
+    }
+}
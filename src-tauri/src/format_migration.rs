@@ -0,0 +1,159 @@
+#![allow(clippy::all)]
+use crate::config_models::Config;
+use crate::todo_parsing::{parse_todo_identity, parse_todo_fields, set_field_in_content, format_field_timestamp, is_priority_like};
+use crate::todo_processing::find_and_process_todos;
+use crate::utils::{decode_short_timestamp, generate_nanoid_id};
+
+use fs2::FileExt;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::OpenOptions;
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+// --- Migration from short-timestamp first words to field-based todos ---
+// `1@AqTDc@@ArSOf content` becomes `1#<nanoid> content @created(..) @finished(..)`:
+// `@ts5` ids are decoded into `@created`, `@@ts5` into `@finished`, and todos without a `#nanoid`/`##num` id get a new nanoid.
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MigratedFile {
+    pub path: String,
+    pub todos_migrated: u32,
+    pub diff: String, // `-`/`+` line pairs under `@@ line N @@` headers
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MigrationReport {
+    pub dry_run: bool,
+    pub files: Vec<MigratedFile>, // Only files with changes or errors
+    pub todos_scanned: u32,
+    pub todos_migrated: u32,
+    pub todos_skipped: u32, // Changed on disk since the scan
+    pub ids_assigned: u32,
+    pub timestamps_decoded: u32,
+    pub files_changed: u32,
+    pub files_failed: u32,
+}
+
+struct MigratedContent {
+    content: String,
+    id_assigned: bool,
+    timestamps_decoded: u32,
+}
+
+// Rewrites one todo's content (the text after its marker), or None if it's already in the field-based format
+fn migrate_todo_content(content: &str) -> Option<MigratedContent> {
+    let identity = parse_todo_identity(content);
+    let has_identity_word = identity.id.is_some() || identity.done_stamp.is_some() || identity.priority.as_deref().map_or(false, is_priority_like);
+    let (priority, rest) = if has_identity_word {
+        let rest = content.split_once(char::is_whitespace).map_or("", |(_, r)| r.trim_start());
+        (identity.priority.clone().unwrap_or_default(), rest)
+    } else {
+        (String::new(), content)
+    };
+
+    let created = identity.id.as_deref()
+        .filter(|id| id.starts_with('@'))
+        .and_then(|id| decode_short_timestamp(&id[1..]));
+    let finished = identity.done_stamp.as_deref().and_then(decode_short_timestamp);
+    let kept_id = identity.id.filter(|id| id.starts_with('#'));
+    if kept_id.is_some() && created.is_none() && finished.is_none() { return None; }
+
+    let id_assigned = kept_id.is_none();
+    let id = kept_id.unwrap_or_else(|| generate_nanoid_id());
+    let first_word = format!("{}{}", priority, id);
+    let mut new_content = if rest.is_empty() { first_word } else { format!("{} {}", first_word, rest) };
+
+    let mut timestamps_decoded = 0;
+    for (name, timestamp) in [("created", created), ("finished", finished)] {
+        let Some(field_value) = timestamp.and_then(|ts| format_field_timestamp(ts as i64)) else { continue };
+        timestamps_decoded += 1;
+        if !parse_todo_fields(&new_content).contains_key(name) {
+            new_content = set_field_in_content(&new_content, name, Some(&field_value));
+        }
+    }
+    Some(MigratedContent { content: new_content, id_assigned, timestamps_decoded })
+}
+
+// Migrates every todo found by a scan with `config`. With `dry_run`, files are left untouched and only diffs are reported.
+// Each file is locked while it's checked and rewritten in place, as the other file operations do, so symlinked files,
+// hard links and ownership survive; a dry run opens files read-only under a shared lock.
+pub fn migrate_todo_format(config: &Config, dry_run: bool) -> io::Result<MigrationReport> {
+    let processed = find_and_process_todos(config, false)?;
+    let marker_re = Regex::new(&config.get_effective_rg_pattern())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid todo pattern for migration: {}", e)))?;
+
+    // file -> [(line number, scanned content)]
+    let mut todos_by_file: BTreeMap<String, Vec<(usize, String)>> = BTreeMap::new();
+    let mut seen_locations: HashSet<&str> = HashSet::new();
+    for todo in processed.categories.iter().flat_map(|c| c.todos.iter()) {
+        if !seen_locations.insert(todo.location.as_str()) { continue; } // Another project's copy of a todo
+        if let Some((file, line)) = todo.location.rsplit_once(':') {
+            if let Ok(line_number) = line.parse::<usize>() {
+                todos_by_file.entry(file.to_string()).or_default().push((line_number, todo.content.clone()));
+            }
+        }
+    }
+
+    let mut report = MigrationReport { dry_run, ..Default::default() };
+    for (file, todos) in todos_by_file {
+        report.todos_scanned += todos.len() as u32;
+        match migrate_file(&file, &todos, &marker_re, dry_run, &mut report) {
+            Ok(Some(migrated_file)) => { report.files_changed += 1; report.files.push(migrated_file); }
+            Ok(None) => {}
+            Err(e) => {
+                report.files_failed += 1;
+                report.files.push(MigratedFile { path: file, error: Some(e.to_string()), ..Default::default() });
+            }
+        }
+    }
+    Ok(report)
+}
+
+fn migrate_file(file: &str, todos: &[(usize, String)], marker_re: &Regex, dry_run: bool, report: &mut MigrationReport) -> io::Result<Option<MigratedFile>> {
+    let file_path = Path::new(file);
+    let mut file_handle = OpenOptions::new().read(true).write(!dry_run).open(file_path)?;
+    if dry_run { file_handle.lock_shared()?; } else { file_handle.lock_exclusive()?; }
+    let result = (|| {
+        let mut original = String::new();
+        BufReader::new(&file_handle).read_to_string(&mut original)?;
+        let mut lines: Vec<String> = original.lines().map(String::from).collect();
+
+        let mut migrated_file = MigratedFile { path: file.to_string(), ..Default::default() };
+        let (mut ids_assigned, mut timestamps_decoded, mut skipped) = (0, 0, 0);
+        for (line_number, scanned_content) in todos {
+            let Some(line) = line_number.checked_sub(1).and_then(|i| lines.get(i)) else { skipped += 1; continue };
+            let Some(mat) = marker_re.find(line) else { skipped += 1; continue };
+            let content_start = line[mat.end()..].find(|c: char| !c.is_whitespace()).map_or(line.len(), |i| mat.end() + i);
+            let content = line[content_start..].trim();
+            if content != scanned_content.trim() { skipped += 1; continue; }
+            let Some(migrated) = migrate_todo_content(content) else { continue };
+
+            let separator = if line[..content_start].ends_with(char::is_whitespace) { "" } else { " " };
+            let new_line = format!("{}{}{}", &line[..content_start], separator, migrated.content);
+            migrated_file.diff.push_str(&format!("@@ line {} @@\n-{}\n+{}\n", line_number, line, new_line));
+            lines[line_number - 1] = new_line;
+            migrated_file.todos_migrated += 1;
+            if migrated.id_assigned { ids_assigned += 1; }
+            timestamps_decoded += migrated.timestamps_decoded;
+        }
+        report.todos_skipped += skipped;
+        if migrated_file.todos_migrated == 0 { return Ok(None); }
+        report.todos_migrated += migrated_file.todos_migrated;
+        report.ids_assigned += ids_assigned;
+        report.timestamps_decoded += timestamps_decoded;
+
+        if !dry_run {
+            let new_full_content = lines.join("\n");
+            let final_write_content = if original.ends_with('\n') && !new_full_content.is_empty() { format!("{}\n", new_full_content) } else { new_full_content };
+            file_handle.set_len(0)?;
+            file_handle.seek(SeekFrom::Start(0))?;
+            file_handle.write_all(final_write_content.as_bytes())?;
+        }
+        Ok(Some(migrated_file))
+    })();
+    fs2::FileExt::unlock(&file_handle)?;
+    result
+}
@@ -0,0 +1,185 @@
+#![allow(clippy::all)]
+use crate::config_models::GitCategoryConfig;
+use crate::utils::get_parent_dir;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// --- Git repository detection ---
+// A working tree is marked by `.git`: a directory for an ordinary clone, or a file holding `gitdir: <path>` for linked
+// worktrees (the git dir has a `commondir` file), submodules (the git dir sits in the superproject's `.git/modules`)
+// and clones with a separate git dir, such as the "bare repository plus worktrees" layout. A bare repository has no
+// working tree and is recognised by its own layout. Only local files are read; git itself is never run.
+// Remote URLs come from the repository's config and are compared in a normalised form, so that the https and ssh
+// URLs of one repository agree.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GitRepoKind {
+    Main,
+    Worktree,  // A linked worktree (`git worktree add`)
+    Submodule,
+    Bare,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitRepoInfo {
+    pub root: PathBuf,    // Working tree root, or the repository itself when bare
+    pub git_dir: PathBuf, // Where this working tree's HEAD lives
+    pub kind: GitRepoKind,
+}
+
+// Reads the `gitdir: <path>` line of a `.git` file; a relative path is relative to the file's directory
+fn read_gitdir_file(dot_git: &Path) -> Option<PathBuf> {
+    let text = fs::read_to_string(dot_git).ok()?;
+    let target = text.lines().find_map(|line| line.strip_prefix("gitdir:"))?.trim();
+    if target.is_empty() { return None; }
+    Some(dot_git.parent()?.join(target))
+}
+
+fn is_bare_repo_dir(dir: &Path) -> bool {
+    if !(dir.join("HEAD").is_file() && dir.join("objects").is_dir() && dir.join("refs").is_dir()) { return false; }
+    fs::read_to_string(dir.join("config")).map_or(false, |config| {
+        config.lines().any(|line| line.split_whitespace().collect::<String>().eq_ignore_ascii_case("bare=true"))
+    })
+}
+
+fn kind_of_git_dir(git_dir: &Path) -> GitRepoKind {
+    if git_dir.join("commondir").is_file() { return GitRepoKind::Worktree; }
+    let components: Vec<_> = git_dir.components().map(|c| c.as_os_str().to_owned()).collect();
+    if components.windows(2).any(|pair| pair[0] == ".git" && pair[1] == "modules") { GitRepoKind::Submodule } else { GitRepoKind::Main }
+}
+
+// The repository containing `start_path` (a file or directory), found by walking up from it
+pub fn find_git_repo(start_path: &Path) -> io::Result<Option<GitRepoInfo>> {
+    let mut current_path = get_parent_dir(start_path)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid start path for git root search. Cannot get parent directory."))?;
+    loop {
+        let dot_git = current_path.join(".git");
+        if dot_git.is_dir() {
+            return Ok(Some(GitRepoInfo { root: current_path, git_dir: dot_git, kind: GitRepoKind::Main }));
+        }
+        if dot_git.is_file() {
+            if let Some(git_dir) = read_gitdir_file(&dot_git) {
+                let kind = kind_of_git_dir(&git_dir);
+                return Ok(Some(GitRepoInfo { root: current_path, git_dir, kind }));
+            }
+        }
+        if current_path.file_name().map_or(true, |name| name != ".git") && is_bare_repo_dir(&current_path) {
+            return Ok(Some(GitRepoInfo { root: current_path.clone(), git_dir: current_path, kind: GitRepoKind::Bare }));
+        }
+        match current_path.parent() {
+            Some(parent) if parent != current_path => current_path = parent.to_path_buf(),
+            _ => return Ok(None), // Reached the filesystem root
+        }
+    }
+}
+
+impl GitRepoInfo {
+    // Branch checked out in this working tree, or the abbreviated commit when HEAD is detached
+    pub fn branch(&self) -> Option<String> {
+        let head = fs::read_to_string(self.git_dir.join("HEAD")).ok()?;
+        let head = head.trim();
+        match head.strip_prefix("ref:") {
+            Some(reference) => { let reference = reference.trim(); Some(reference.strip_prefix("refs/heads/").unwrap_or(reference).to_string()) }
+            None if head.len() >= 7 => Some(head[..7].to_string()),
+            None => None,
+        }
+    }
+
+    // A linked worktree's name, as `git worktree list` knows it
+    pub fn worktree_name(&self) -> Option<String> {
+        if self.kind != GitRepoKind::Worktree { return None; }
+        self.git_dir.file_name().map(|name| name.to_string_lossy().into_owned())
+    }
+
+    // The git dir shared by all worktrees of a repository, which holds its config
+    pub fn common_dir(&self) -> PathBuf {
+        match fs::read_to_string(self.git_dir.join("commondir")) {
+            Ok(common_dir) => self.git_dir.join(common_dir.trim()),
+            Err(_) => self.git_dir.clone(),
+        }
+    }
+
+    // Remotes in the repository's config as (name, url), in file order
+    pub fn remotes(&self) -> Vec<(String, String)> {
+        let config = match fs::read_to_string(self.common_dir().join("config")) { Ok(config) => config, Err(_) => return Vec::new() };
+        let mut remotes = Vec::new();
+        let mut current_remote: Option<String> = None;
+        for line in config.lines().map(str::trim) {
+            if line.starts_with('[') {
+                current_remote = line.strip_prefix("[remote")
+                    .filter(|rest| rest.starts_with(char::is_whitespace))
+                    .and_then(|rest| rest.trim().strip_suffix(']'))
+                    .map(|name| name.trim().trim_matches('"').to_string());
+            } else if let (Some(name), Some((key, value))) = (&current_remote, line.split_once('=')) {
+                if key.trim().eq_ignore_ascii_case("url") { remotes.push((name.clone(), value.trim().trim_matches('"').to_string())); }
+            }
+        }
+        remotes
+    }
+
+    pub fn origin_url(&self) -> Option<String> {
+        self.remotes().into_iter().find(|(name, _)| name == "origin").map(|(_, url)| url)
+    }
+
+    // Name of the repository a linked worktree belongs to: the main working tree's directory, or the bare
+    // repository's name without `.git`
+    pub fn main_repo_name(&self) -> Option<String> {
+        if self.kind != GitRepoKind::Worktree { return None; }
+        let common_dir = self.common_dir();
+        let common_dir = common_dir.canonicalize().unwrap_or(common_dir);
+        let name = common_dir.file_name()?.to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            return common_dir.parent().and_then(Path::file_name).map(|n| n.to_string_lossy().into_owned());
+        }
+        Some(name.strip_suffix(".git").unwrap_or(&name).to_string())
+    }
+
+    // The repository a submodule is checked out in
+    pub fn superproject(&self) -> Option<GitRepoInfo> {
+        if self.kind != GitRepoKind::Submodule { return None; }
+        find_git_repo(self.root.parent()?).ok().flatten()
+    }
+}
+
+// The repository a file's todos are filed under: its own, or with `group_submodules` the outermost superproject.
+// The root is canonical, so it can serve as a category key.
+pub fn category_repo(file_path: &Path, git: &GitCategoryConfig) -> Option<GitRepoInfo> {
+    let mut repo = find_git_repo(file_path).ok().flatten()?;
+    if git.group_submodules {
+        while let Some(superproject) = repo.superproject() { repo = superproject; }
+    }
+    repo.root = repo.root.canonicalize().unwrap_or(repo.root);
+    Some(repo)
+}
+
+// `https://github.com/acme/api.git`, `git@github.com:acme/api` and `ssh://git@GitHub.com:22/acme/api/` all become
+// `github.com/acme/api`: no scheme, user, port, `.git` suffix or trailing slash, and a lowercase host. Local paths
+// only lose the suffix and slash.
+pub fn normalise_remote_url(url: &str) -> String {
+    let url = url.trim();
+    let (host, path) = match url.split_once("://") {
+        Some((_, rest)) if rest.starts_with('/') => ("", rest), // `file:///srv/git/api.git`
+        Some((_, rest)) => rest.split_once('/').unwrap_or((rest, "")),
+        None => match url.split_once(':') {
+            Some((host, path)) if !host.contains('/') && host.len() > 1 => (host, path), // scp-like `user@host:path`; not `C:`
+            _ => ("", url),
+        },
+    };
+    let host = host.rsplit_once('@').map_or(host, |(_, host)| host);
+    let host = host.split_once(':').map_or(host, |(host, _)| host).to_lowercase();
+    let path = path.trim_end_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    if host.is_empty() { path.to_string() } else { format!("{}/{}", host, path.trim_start_matches('/')) }
+}
+
+// `<repository>/<worktree>` for a linked worktree's root, e.g. `api/hotfix`
+pub fn worktree_display_name(root: &Path) -> Option<String> {
+    let repo = find_git_repo(root).ok().flatten()?;
+    Some(format!("{}/{}", repo.main_repo_name()?, repo.worktree_name()?))
+}
+
+pub fn branch_of(root: &Path) -> Option<String> {
+    find_git_repo(root).ok().flatten()?.branch()
+}
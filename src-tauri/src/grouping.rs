@@ -0,0 +1,275 @@
+#![allow(clippy::all)]
+use crate::config_models::{CategoryOrderConfig, Config};
+use crate::todo_models::{QueryMatch, TodoCategoryData, TodoCategoryEnum, TodoItem, TodoState, ViewGroup};
+use crate::todo_parsing::{is_priority_like, parse_todo_identity, parse_todo_tags};
+use crate::git_repo::category_repo;
+use crate::todo_processing::{name_repo_categories, repo_category, summarize_category};
+
+use chrono::{Duration, Local, NaiveDateTime, TimeZone};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Component, Path};
+
+// --- Grouping strategies for todo lists and views ---
+// A scan groups todos by project (configured project, else git repository, else Other). Requests and views can
+// regroup them by another strategy; each group gets a name, an icon, and a rank that orders groups before names do.
+// Groups are told apart by key: a scan category's key, `git:<root>` for repositories, else the group's name.
+
+const ICON_GIT: &str = "\u{f02a2}";
+const ICON_FOLDER: &str = "\u{f07b}";
+const ICON_FILE: &str = "\u{f15b}";
+const ICON_CODE: &str = "\u{f121}";
+const ICON_STATE: &str = "\u{f046}";
+const ICON_FLAG: &str = "\u{f024}";
+const ICON_TAG: &str = "\u{f02b}";
+const ICON_CALENDAR: &str = "\u{f073}";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    Project,   // The scan's categories
+    GitRepo,   // Git repository, ignoring configured projects
+    Directory, // Top-level directory under the search path the todo was found in
+    File,
+    Language,  // From the file extension
+    State,
+    Priority,
+    Tag,       // `#tag` words; a todo with several tags is in each of their groups
+    Due,       // Overdue, earlier (closed), today, next 7 days, later, no due date
+    None,      // One group with every todo
+}
+
+pub fn parse_group_by(text: &str) -> io::Result<GroupBy> {
+    match text.trim().to_lowercase().as_str() {
+        "" | "project" | "category" => Ok(GroupBy::Project),
+        "repo" | "git" => Ok(GroupBy::GitRepo),
+        "directory" | "dir" => Ok(GroupBy::Directory),
+        "file" => Ok(GroupBy::File),
+        "language" | "extension" | "ext" => Ok(GroupBy::Language),
+        "state" | "status" => Ok(GroupBy::State),
+        "priority" | "prio" => Ok(GroupBy::Priority),
+        "tag" => Ok(GroupBy::Tag),
+        "due" => Ok(GroupBy::Due),
+        "none" => Ok(GroupBy::None),
+        other => Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+            "Unknown grouping '{}' (expected project, repo, directory, file, language, state, priority, tag, due or none)", other))),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct GroupKey {
+    rank: usize,
+    key: String,
+    name: String,
+    icon: String,
+    repo: Option<TodoCategoryEnum>, // Set by the repo strategy, whose names are finished once all groups are known
+}
+
+// What grouping needs besides the todo itself; repositories are looked up once per file
+pub struct GroupContext<'a> {
+    config: &'a Config,
+    now: NaiveDateTime,
+    ungrouped_name: String,
+    scan_categories: HashMap<String, (usize, String, String)>, // Scan category name -> (position, icon, key)
+    repo_categories: HashMap<String, Option<TodoCategoryEnum>>,
+}
+
+impl<'a> GroupContext<'a> {
+    // `categories` are the scan's categories, whose order and icons the project grouping keeps
+    pub fn new(config: &'a Config, categories: &[TodoCategoryData], ungrouped_name: &str, now: NaiveDateTime) -> Self {
+        GroupContext {
+            config,
+            now,
+            ungrouped_name: ungrouped_name.to_string(),
+            scan_categories: categories.iter().enumerate().map(|(i, c)| (c.name.clone(), (i, c.icon.clone(), c.key.clone()))).collect(),
+            repo_categories: HashMap::new(),
+        }
+    }
+
+    fn repo_category(&mut self, file: &str) -> Option<TodoCategoryEnum> {
+        let git = &self.config.git;
+        self.repo_categories.entry(file.to_string())
+            .or_insert_with(|| category_repo(Path::new(file), git).map(|repo| repo_category(&repo, git)))
+            .clone()
+    }
+
+    // The search path `file` was found under (the longest one that contains it) and the path below it
+    fn split_search_root<'f>(&self, file: &'f str) -> Option<(String, &'f Path)> {
+        self.config.rg.paths.iter()
+            .filter_map(|root| Path::new(file).strip_prefix(root).ok().map(|rest| (root.clone(), rest)))
+            .max_by_key(|(root, _)| root.len())
+    }
+
+    fn keys(&mut self, group_by: GroupBy, item: &TodoItem, category: &str) -> Vec<GroupKey> {
+        let key = |rank: usize, name: String, icon: &str| GroupKey { rank, key: name.clone(), name, icon: icon.to_string(), repo: None };
+        let file = item.location.rsplit_once(':').map_or(item.location.as_str(), |(file, _)| file);
+        match group_by {
+            GroupBy::Project => {
+                let (position, icon, category_key) = self.scan_categories.get(category).cloned()
+                    .unwrap_or_else(|| (usize::MAX, String::new(), category.to_string()));
+                vec![GroupKey { rank: position, key: category_key, name: category.to_string(), icon, repo: None }]
+            }
+            GroupBy::GitRepo => match self.repo_category(file) {
+                Some(category) => vec![GroupKey { rank: 0, key: category.key(), name: category.get_details().0, icon: ICON_GIT.to_string(), repo: Some(category) }],
+                None => vec![key(1, "Other".to_string(), ICON_FILE)],
+            },
+            GroupBy::Directory => {
+                let name = match self.split_search_root(file) {
+                    Some((root, rest)) => match rest.components().next() {
+                        Some(Component::Normal(first)) if rest.components().count() > 1 => first.to_string_lossy().into_owned(),
+                        _ => root,
+                    },
+                    None => Path::new(file).parent().map_or_else(String::new, |p| p.display().to_string()),
+                };
+                vec![key(0, name, ICON_FOLDER)]
+            }
+            GroupBy::File => {
+                let name = self.split_search_root(file).map_or_else(|| file.to_string(), |(_, rest)| rest.display().to_string());
+                vec![key(0, name, ICON_FILE)]
+            }
+            GroupBy::Language => match Path::new(file).extension().map(|e| e.to_string_lossy().to_lowercase()) {
+                Some(extension) => vec![key(0, language_name(&extension), ICON_CODE)],
+                None => vec![key(1, "No extension".to_string(), ICON_CODE)],
+            },
+            GroupBy::State => {
+                let state = self.config.todo_state_of(&item.status);
+                let name = match state {
+                    Some(TodoState::Open) => "Open",
+                    Some(TodoState::Active) => "Active",
+                    Some(TodoState::Done) => "Done",
+                    Some(TodoState::Cancelled) => "Cancelled",
+                    None => "Unknown",
+                };
+                vec![key(state.map_or(4, TodoState::index), name.to_string(), ICON_STATE)]
+            }
+            GroupBy::Priority => match parse_todo_identity(&item.content).priority.filter(|p| is_priority_like(p)) {
+                Some(priority) => vec![key(0, priority, ICON_FLAG)],
+                None => vec![key(1, "No priority".to_string(), ICON_FLAG)],
+            },
+            GroupBy::Tag => {
+                let tags = parse_todo_tags(&item.content);
+                if tags.is_empty() { return vec![key(1, "Untagged".to_string(), ICON_TAG)]; }
+                tags.into_iter().map(|tag| key(0, format!("#{}", tag), ICON_TAG)).collect()
+            }
+            GroupBy::Due => {
+                let today = self.now.date();
+                let due_date = item.due.and_then(|t| Local.timestamp_opt(t, 0).single()).map(|d| d.date_naive());
+                let (rank, name) = match due_date {
+                    _ if item.overdue => (0, "Overdue"),
+                    None => (5, "No due date"),
+                    Some(date) if date < today => (1, "Earlier"), // Closed todos that were due before today
+                    Some(date) if date == today => (2, "Today"),
+                    Some(date) if date <= today + Duration::days(7) => (3, "Next 7 days"),
+                    Some(_) => (4, "Later"),
+                };
+                vec![key(rank, name.to_string(), ICON_CALENDAR)]
+            }
+            GroupBy::None => vec![key(0, self.ungrouped_name.clone(), "")],
+        }
+    }
+
+    // Regroups a scan's categories, keeping the order of todos; groups come in their strategy's order. A todo listed
+    // under several projects is grouped once, as its first project's copy.
+    pub fn regroup_categories(&mut self, categories: Vec<TodoCategoryData>, group_by: GroupBy) -> Vec<TodoCategoryData> {
+        if group_by == GroupBy::Project { return categories; }
+        let mut groups: Vec<(GroupKey, Vec<TodoItem>)> = Vec::new();
+        let mut index_by_key: HashMap<String, usize> = HashMap::new();
+        let mut seen_locations: HashSet<String> = HashSet::new();
+        for category in categories {
+            for item in category.todos {
+                if !seen_locations.insert(item.location.clone()) { continue; }
+                for key in self.keys(group_by, &item, &category.name) {
+                    let index = *index_by_key.entry(key.key.clone()).or_insert_with(|| { groups.push((key, Vec::new())); groups.len() - 1 });
+                    groups[index].1.push(item.clone());
+                }
+            }
+        }
+        finish_groups(&mut groups, self.config);
+        groups.into_iter().map(|(key, todos)| summarize_category(key.key, key.name, key.icon, todos, self.config)).collect()
+    }
+
+    // Groups query matches, keeping their order; groups come in their strategy's order. Except by project, a todo
+    // matched under several projects is grouped once.
+    pub fn group_matches(&mut self, matches: Vec<QueryMatch>, group_by: GroupBy) -> Vec<ViewGroup> {
+        let mut groups: Vec<(GroupKey, Vec<QueryMatch>)> = Vec::new();
+        let mut index_by_key: HashMap<String, usize> = HashMap::new();
+        let mut seen_locations: HashSet<String> = HashSet::new();
+        for query_match in matches {
+            if group_by != GroupBy::Project && !seen_locations.insert(query_match.item.location.clone()) { continue; }
+            for key in self.keys(group_by, &query_match.item, &query_match.category) {
+                let index = *index_by_key.entry(key.key.clone()).or_insert_with(|| { groups.push((key, Vec::new())); groups.len() - 1 });
+                groups[index].1.push(query_match.clone());
+            }
+        }
+        finish_groups(&mut groups, self.config);
+        groups.into_iter().map(|(key, matches)| ViewGroup { key: key.key, name: key.name, icon: key.icon, matches }).collect()
+    }
+}
+
+// Gives same-named repositories distinct names, then puts the groups in their strategy's order
+fn finish_groups<T>(groups: &mut Vec<(GroupKey, T)>, config: &Config) {
+    let mut names: Vec<String> = groups.iter().map(|(key, _)| key.name.clone()).collect();
+    let repos: Vec<Option<&TodoCategoryEnum>> = groups.iter().map(|(key, _)| key.repo.as_ref()).collect();
+    name_repo_categories(&mut names, &repos, &config.git);
+    for ((key, _), name) in groups.iter_mut().zip(names) { key.name = name; }
+    groups.sort_by(|(a, _), (b, _)| compare_group_keys(a, b));
+}
+
+fn compare_group_keys(a: &GroupKey, b: &GroupKey) -> Ordering {
+    a.rank.cmp(&b.rank).then_with(|| natord::compare_ignore_case(&a.name, &b.name))
+}
+
+fn language_name(extension: &str) -> String {
+    let name = match extension {
+        "rs" => "Rust",
+        "md" | "markdown" => "Markdown",
+        "ts" | "tsx" | "mts" | "cts" => "TypeScript",
+        "js" | "jsx" | "mjs" | "cjs" => "JavaScript",
+        "py" | "pyi" => "Python",
+        "go" => "Go",
+        "java" => "Java",
+        "kt" | "kts" => "Kotlin",
+        "c" | "h" => "C",
+        "cc" | "cpp" | "cxx" | "hpp" | "hh" => "C++",
+        "cs" => "C#",
+        "rb" => "Ruby",
+        "php" => "PHP",
+        "swift" => "Swift",
+        "sh" | "bash" | "zsh" => "Shell",
+        "html" | "htm" => "HTML",
+        "css" | "scss" | "sass" => "CSS",
+        "json" => "JSON",
+        "toml" => "TOML",
+        "yaml" | "yml" => "YAML",
+        "txt" => "Text",
+        "lua" => "Lua",
+        "sql" => "SQL",
+        "proto" => "Protobuf",
+        _ => return format!(".{}", extension),
+    };
+    name.to_string()
+}
+
+// Orders groups by the configured sort, then moves pinned names to the front in their listed order
+fn order_groups<T>(groups: &mut Vec<T>, order: &CategoryOrderConfig, name: impl Fn(&T) -> &str, open: impl Fn(&T) -> usize, total: impl Fn(&T) -> usize) {
+    match order.sort.as_str() {
+        "" => {}
+        "name" => groups.sort_by(|a, b| natord::compare_ignore_case(name(a), name(b))),
+        "open" => groups.sort_by(|a, b| open(b).cmp(&open(a))),
+        "count" => groups.sort_by(|a, b| total(b).cmp(&total(a))),
+        other => log::warn!("Unknown category sort '{}' (expected name, open or count); keeping the grouping's order", other),
+    }
+    let pin_position = |group: &T| order.pinned.iter().position(|p| p.eq_ignore_ascii_case(name(group))).unwrap_or(usize::MAX);
+    groups.sort_by_key(|group| pin_position(group));
+}
+
+pub fn order_categories(categories: &mut Vec<TodoCategoryData>, order: &CategoryOrderConfig) {
+    order_groups(categories, order, |c| c.name.as_str(),
+        |c| (c.state_counts.open + c.state_counts.active) as usize,
+        |c| (c.state_counts.open + c.state_counts.active + c.state_counts.done + c.state_counts.cancelled) as usize);
+}
+
+pub fn order_view_groups(groups: &mut Vec<ViewGroup>, order: &CategoryOrderConfig, config: &Config) {
+    let is_open = |m: &QueryMatch| config.todo_state_of(&m.item.status).map_or(false, |s| !s.is_closed());
+    order_groups(groups, order, |g| g.name.as_str(), |g| g.matches.iter().filter(|m| is_open(m)).count(), |g| g.matches.len());
+}
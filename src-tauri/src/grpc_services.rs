@@ -1,8 +1,20 @@
 #![allow(clippy::all)]
-use crate::config_models::{AppConfiguration, Config, RgConfig, ProjectConfig as ModelProjectConfig}; // Added RgConfig, aliased ProjectConfig to avoid conflict
-use crate::todo_models::{TodoItem as InternalTodoItem, TodoCategoryData as InternalTodoCategoryData}; // Aliased internal models
-use crate::todo_processing::find_and_process_todos;
-use crate::file_operations::{edit_todo_in_file_grpc, add_todo_to_file_grpc, mark_todo_as_done_in_file_grpc, cycle_todo_state_in_file_grpc};
+use crate::config_models::{AppConfiguration, Config, RgConfig, ProjectConfig as ModelProjectConfig, ViewConfig, CategoryOrderConfig, GitCategoryConfig, PackageDiscoveryConfig}; // Added RgConfig, aliased ProjectConfig to avoid conflict
+use crate::todo_models::{TodoState, TodoFieldKind, TodoField as InternalTodoField, TodoItem as InternalTodoItem, SubtaskRollup as InternalSubtaskRollup, TodoDetails as InternalTodoDetails, TodoTreeNode as InternalTodoTreeNode, TodoCategoryData as InternalTodoCategoryData, ScanDiagnostic as InternalScanDiagnostic, DependencyNode as InternalDependencyNode, AgendaEntry as InternalAgendaEntry, QueryMatch as InternalQueryMatch, ViewGroup as InternalViewGroup, TimeTotal as InternalTimeTotal, HistoryEvent as InternalHistoryEvent, HistoryEventKind, DisappearedTodo as InternalDisappearedTodo, StateCounts as InternalStateCounts}; // Aliased internal models
+use crate::todo_processing::{find_and_process_todos, build_todo_tree, remove_deferred_todos};
+use crate::todo_parsing::{parse_field_date, field_date_to_timestamp, parse_field_duration, parse_field_number};
+use crate::format_migration::{migrate_todo_format, MigratedFile as InternalMigratedFile};
+use crate::agenda::{build_agenda, DEFAULT_AGENDA_DAYS, MAX_AGENDA_DAYS};
+use crate::todo_query::{parse_query, query_todos};
+use crate::views::{find_view, evaluate_view};
+use crate::grouping::{parse_group_by, order_categories, GroupContext};
+use crate::todo_sort::{parse_sort_spec, sort_category_todos, sort_query_matches};
+use crate::natural_dates::parse_natural_date;
+use crate::history::{read_history, HistoryFilter};
+use crate::scan_changes::{list_disappeared_todos, record_scan_changes, restore_disappeared_todo, confirm_disappeared_todo_done};
+use crate::time_tracking::{load_time_log, start_timer, stop_timer, time_spent_by_todo, find_todo_by_id, build_time_report};
+use crate::dependency_graph::{build_dependency_graph, find_open_prerequisites, remember_scan_dependencies};
+use crate::file_operations::{ContentModified, edit_todo_in_file_grpc, add_todo_to_file_grpc, mark_todo_as_done_in_file_grpc, cycle_todo_state_in_file_grpc, update_todo_fields_in_file_grpc, set_todo_state_in_file_grpc};
 use crate::config_io::{write_config_to_path_internal, get_primary_config_path, CONFIG_FILE_MUTEX}; // Corrected imports
 
 use std::sync::Arc;
@@ -16,6 +28,7 @@ use crate::unitodo::{
     todo_service_server::TodoService,
     AddTodoRequest,
     AddTodoResponse,
+    ParsedDue as ProtoParsedDue,
     ConfigMessage as ProtoConfigMessage,
     EditTodoRequest,
     EditTodoResponse,
@@ -26,10 +39,15 @@ use crate::unitodo::{
     MarkDoneRequest,
     MarkDoneResponse,
     ProjectConfigMessage as ProtoProjectConfigMessage, // Assuming this is how it's named in proto
+    ScanDiagnostic as ProtoScanDiagnostic,
     RgConfigMessage as ProtoRgConfigMessage,       // Assuming this is how it's named in proto
     TodoCategory as ProtoTodoCategory,
     TodoStateSet as ProtoTodoStateSet, // New type from regenerated proto
     TodoItem as ProtoTodoItem,
+    TodoDetails as ProtoTodoDetails,
+    TodoNode as ProtoTodoNode,
+    SubtaskRollup as ProtoSubtaskRollup,
+    get_todos_request::Shape as GetTodosShape,
     UpdateConfigRequest,
     UpdateConfigResponse,
     GetActiveProfileRequest, GetActiveProfileResponse,
@@ -38,22 +56,305 @@ use crate::unitodo::{
     AddProfileRequest, AddProfileResponse,
     DeleteProfileRequest, DeleteProfileResponse,
     CycleTodoStateRequest, CycleTodoStateResponse, // Added for the new RPC
+    GetDependencyGraphRequest, GetDependencyGraphResponse,
+    UpdateTodoFieldsRequest, UpdateTodoFieldsResponse,
+    MigrateFormatRequest, MigrateFormatResponse,
+    GetAgendaRequest, GetAgendaResponse,
+    QueryTodosRequest, QueryTodosResponse,
+    QueryMatch as ProtoQueryMatch,
+    TextRange as ProtoTextRange,
+    GetViewRequest, GetViewResponse,
+    ViewGroup as ProtoViewGroup,
+    ViewConfigMessage as ProtoViewConfigMessage,
+    ViewColumns as ProtoViewColumns,
+    ViewList as ProtoViewList,
+    PatternList as ProtoPatternList,
+    CategoryOrderMessage as ProtoCategoryOrderMessage,
+    GitCategoryMessage as ProtoGitCategoryMessage,
+    PackageDiscoveryMessage as ProtoPackageDiscoveryMessage,
+    StateCounts as ProtoStateCounts,
+    SnoozeRequest, SnoozeResponse,
+    StartTimerRequest, StartTimerResponse,
+    StopTimerRequest, StopTimerResponse,
+    GetTimeReportRequest, GetTimeReportResponse,
+    TimeTotal as ProtoTimeTotal,
+    GetHistoryRequest, GetHistoryResponse,
+    HistoryEvent as ProtoHistoryEvent,
+    history_event::Kind as ProtoHistoryEventKind,
+    GetDisappearedTodosRequest, GetDisappearedTodosResponse,
+    ResolveDisappearedTodoRequest, ResolveDisappearedTodoResponse,
+    DisappearedTodo as ProtoDisappearedTodo,
+    resolve_disappeared_todo_request::Action as ResolveDisappearedAction,
+    AgendaEntry as ProtoAgendaEntry,
+    AgendaDay as ProtoAgendaDay,
+    MigratedFile as ProtoMigratedFile,
+    TodoField as ProtoTodoField,
+    todo_field::Kind as ProtoTodoFieldKind,
+    DependencyNode as ProtoDependencyNode,
+    DependencyEdge as ProtoDependencyEdge,
+    DependencyCycle as ProtoDependencyCycle,
+    DanglingReference as ProtoDanglingReference,
 };
 
 // --- Mapping Functions (Internal Models <-> Proto Models) ---
+fn to_proto_todo_details(details: &InternalTodoDetails) -> ProtoTodoDetails {
+    ProtoTodoDetails {
+        text: details.text.clone(),
+        start_line: details.start_line as u32,
+        end_line: details.end_line as u32,
+    }
+}
+
+fn to_proto_subtask_rollup(rollup: &InternalSubtaskRollup) -> ProtoSubtaskRollup {
+    ProtoSubtaskRollup {
+        total: rollup.total,
+        done: rollup.done,
+    }
+}
+
+fn to_proto_todo_field(field: &InternalTodoField) -> ProtoTodoField {
+    let mut proto_field = ProtoTodoField { raw: field.raw.clone(), ..Default::default() };
+    match field.kind {
+        TodoFieldKind::Text => proto_field.set_kind(ProtoTodoFieldKind::Text),
+        TodoFieldKind::Date => {
+            proto_field.set_kind(ProtoTodoFieldKind::Date);
+            proto_field.timestamp = parse_field_date(&field.raw).as_ref().and_then(field_date_to_timestamp);
+        }
+        TodoFieldKind::Duration => {
+            proto_field.set_kind(ProtoTodoFieldKind::Duration);
+            proto_field.duration_seconds = parse_field_duration(&field.raw);
+        }
+        TodoFieldKind::Number => {
+            proto_field.set_kind(ProtoTodoFieldKind::Number);
+            proto_field.number = parse_field_number(&field.raw);
+        }
+    }
+    proto_field
+}
+
 fn to_proto_todo_item(item: &InternalTodoItem) -> ProtoTodoItem {
     ProtoTodoItem {
         content: item.content.clone(),
         location: item.location.clone(),
         status: item.status.clone(),
+        details: item.details.as_ref().map(to_proto_todo_details),
+        parent: item.parent.clone(),
+        depth: item.depth,
+        subtasks: item.subtasks.as_ref().map(to_proto_subtask_rollup),
+        id: item.id.clone(),
+        fields: item.fields.iter().map(|(name, field)| (name.clone(), to_proto_todo_field(field))).collect(),
+        due: item.due,
+        scheduled: item.scheduled,
+        overdue: item.overdue,
+        deferred: item.deferred,
+        time_spent: item.time_spent,
+        estimate: item.estimate,
+        timer_started: item.timer_started,
+        fingerprint: item.fingerprint.clone(),
+    }
+}
+
+fn to_proto_time_total(total: &InternalTimeTotal) -> ProtoTimeTotal {
+    ProtoTimeTotal {
+        key: total.key.clone(),
+        label: total.label.clone(),
+        seconds: total.seconds,
+        estimate: total.estimate,
+    }
+}
+
+fn to_proto_history_event_kind(kind: HistoryEventKind) -> ProtoHistoryEventKind {
+    match kind {
+        HistoryEventKind::Created => ProtoHistoryEventKind::Created,
+        HistoryEventKind::Edited => ProtoHistoryEventKind::Edited,
+        HistoryEventKind::FieldsChanged => ProtoHistoryEventKind::FieldsChanged,
+        HistoryEventKind::Reopened => ProtoHistoryEventKind::Reopened,
+        HistoryEventKind::Started => ProtoHistoryEventKind::Started,
+        HistoryEventKind::Completed => ProtoHistoryEventKind::Completed,
+        HistoryEventKind::Cancelled => ProtoHistoryEventKind::Cancelled,
+        HistoryEventKind::Appeared => ProtoHistoryEventKind::Appeared,
+        HistoryEventKind::Disappeared => ProtoHistoryEventKind::Disappeared,
+        HistoryEventKind::Moved => ProtoHistoryEventKind::Moved,
+        HistoryEventKind::Restored => ProtoHistoryEventKind::Restored,
+    }
+}
+
+fn from_proto_history_event_kind(kind: ProtoHistoryEventKind) -> HistoryEventKind {
+    match kind {
+        ProtoHistoryEventKind::Created => HistoryEventKind::Created,
+        ProtoHistoryEventKind::Edited => HistoryEventKind::Edited,
+        ProtoHistoryEventKind::FieldsChanged => HistoryEventKind::FieldsChanged,
+        ProtoHistoryEventKind::Reopened => HistoryEventKind::Reopened,
+        ProtoHistoryEventKind::Started => HistoryEventKind::Started,
+        ProtoHistoryEventKind::Completed => HistoryEventKind::Completed,
+        ProtoHistoryEventKind::Cancelled => HistoryEventKind::Cancelled,
+        ProtoHistoryEventKind::Appeared => HistoryEventKind::Appeared,
+        ProtoHistoryEventKind::Disappeared => HistoryEventKind::Disappeared,
+        ProtoHistoryEventKind::Moved => HistoryEventKind::Moved,
+        ProtoHistoryEventKind::Restored => HistoryEventKind::Restored,
+    }
+}
+
+fn to_proto_history_event(event: &InternalHistoryEvent) -> ProtoHistoryEvent {
+    ProtoHistoryEvent {
+        at: event.at,
+        kind: to_proto_history_event_kind(event.kind) as i32,
+        todo_id: event.todo_id.clone(),
+        location: event.location.clone(),
+        project: event.project.clone(),
+        status: event.status.clone(),
+        content: event.content.clone(),
+        previous: event.previous.clone(),
+    }
+}
+
+fn to_proto_disappeared_todo(todo: &InternalDisappearedTodo) -> ProtoDisappearedTodo {
+    ProtoDisappearedTodo {
+        key: todo.key.clone(),
+        todo_id: todo.todo_id.clone(),
+        location: todo.location.clone(),
+        project: todo.project.clone(),
+        status: todo.status.clone(),
+        content: todo.content.clone(),
+        disappeared_at: todo.disappeared_at,
+    }
+}
+
+fn to_proto_agenda_entry(entry: &InternalAgendaEntry) -> ProtoAgendaEntry {
+    ProtoAgendaEntry {
+        item: Some(to_proto_todo_item(&entry.item)),
+        category: entry.category.clone(),
+    }
+}
+
+fn to_proto_query_match(query_match: &InternalQueryMatch) -> ProtoQueryMatch {
+    ProtoQueryMatch {
+        item: Some(to_proto_todo_item(&query_match.item)),
+        category: query_match.category.clone(),
+        highlights: query_match.highlights.iter().map(|r| ProtoTextRange { start: r.start, end: r.end }).collect(),
+    }
+}
+
+fn to_proto_view_group(group: &InternalViewGroup) -> ProtoViewGroup {
+    ProtoViewGroup {
+        key: group.key.clone(),
+        name: group.name.clone(),
+        icon: group.icon.clone(),
+        matches: group.matches.iter().map(to_proto_query_match).collect(),
+    }
+}
+
+fn to_proto_view_config(view: &ViewConfig) -> ProtoViewConfigMessage {
+    ProtoViewConfigMessage {
+        name: view.name.clone(),
+        query: view.query.clone(),
+        sort: view.sort.clone(),
+        group_by: view.group_by.clone(),
+        columns: view.columns.as_ref().map(|columns| ProtoViewColumns { columns: columns.clone() }),
+    }
+}
+
+fn from_proto_view_config(view: ProtoViewConfigMessage) -> ViewConfig {
+    ViewConfig {
+        name: view.name,
+        query: view.query,
+        sort: view.sort,
+        group_by: view.group_by,
+        columns: view.columns.map(|c| c.columns),
+    }
+}
+
+fn to_proto_todo_node(node: &InternalTodoTreeNode) -> ProtoTodoNode {
+    ProtoTodoNode {
+        item: Some(to_proto_todo_item(&node.item)),
+        children: node.children.iter().map(to_proto_todo_node).collect(),
+    }
+}
+
+fn to_proto_state_counts(counts: &InternalStateCounts) -> ProtoStateCounts {
+    ProtoStateCounts {
+        open: counts.open,
+        active: counts.active,
+        done: counts.done,
+        cancelled: counts.cancelled,
     }
 }
 
 fn to_proto_todo_category(category_data: &InternalTodoCategoryData) -> ProtoTodoCategory {
     ProtoTodoCategory {
+        key: category_data.key.clone(),
         name: category_data.name.clone(),
         icon: category_data.icon.clone(),
         todos: category_data.todos.iter().map(to_proto_todo_item).collect(),
+        tree: Vec::new(),
+        deferred_count: category_data.deferred_count,
+        state_counts: Some(to_proto_state_counts(&category_data.state_counts)),
+        parent_key: category_data.parent_key.clone(),
+        parent_name: category_data.parent_name.clone(),
+    }
+}
+
+fn to_proto_todo_category_tree(category_data: &InternalTodoCategoryData) -> ProtoTodoCategory {
+    ProtoTodoCategory {
+        key: category_data.key.clone(),
+        name: category_data.name.clone(),
+        icon: category_data.icon.clone(),
+        todos: Vec::new(),
+        tree: build_todo_tree(&category_data.todos).iter().map(to_proto_todo_node).collect(),
+        deferred_count: category_data.deferred_count,
+        state_counts: Some(to_proto_state_counts(&category_data.state_counts)),
+        parent_key: category_data.parent_key.clone(),
+        parent_name: category_data.parent_name.clone(),
+    }
+}
+
+fn to_proto_scan_diagnostic(diagnostic: &InternalScanDiagnostic) -> ProtoScanDiagnostic {
+    ProtoScanDiagnostic {
+        location: diagnostic.location.clone(),
+        message: diagnostic.message.clone(),
+    }
+}
+
+fn to_proto_dependency_node(node: &InternalDependencyNode) -> ProtoDependencyNode {
+    ProtoDependencyNode {
+        key: node.key.clone(),
+        item: Some(to_proto_todo_item(&node.item)),
+        category: node.category.clone(),
+        open: node.open,
+        unblocked: node.open && node.blocked_by.is_empty(),
+        blocked_by: node.blocked_by.clone(),
+    }
+}
+
+fn to_proto_migrated_file(file: &InternalMigratedFile) -> ProtoMigratedFile {
+    ProtoMigratedFile {
+        path: file.path.clone(),
+        todos_migrated: file.todos_migrated,
+        diff: file.diff.clone(),
+        error: file.error.clone(),
+    }
+}
+
+// The status for a failed file operation; `action` completes "Failed to ..." for unexpected errors
+fn io_error_status(e: io::Error, action: &str) -> Status {
+    if e.get_ref().map_or(false, |inner| inner.is::<ContentModified>()) { return Status::aborted(e.to_string()); }
+    match e.kind() {
+        io::ErrorKind::NotFound => Status::not_found(e.to_string()),
+        io::ErrorKind::InvalidInput => Status::invalid_argument(e.to_string()),
+        io::ErrorKind::PermissionDenied => Status::permission_denied(e.to_string()),
+        _ => Status::internal(format!("Failed to {}: {}", action, e)),
+    }
+}
+
+// With `enforce_dependencies`, refuses an operation that would close the todo at `location` while it has open prerequisites.
+// `closes` tells, from the todo's current marker, whether the operation closes it.
+fn ensure_prerequisites_closed(config: &Config, location: &str, closes: impl FnOnce(&str) -> bool) -> Result<(), Status> {
+    if !config.enforce_dependencies { return Ok(()); }
+    let (status, blocked_by) = find_open_prerequisites(config, location)
+        .map_err(|e| Status::internal(format!("Failed to check dependencies: {}", e)))?;
+    match status {
+        Some(marker) if !blocked_by.is_empty() && closes(&marker) => Err(Status::failed_precondition(format!("Todo is blocked by open prerequisites: {}", blocked_by.join(", ")))),
+        _ => Ok(()),
     }
 }
 
@@ -68,6 +369,9 @@ fn to_proto_config(config: &Config) -> ProtoConfigMessage {
         projects: config.projects.iter().map(|(k, v)| (k.clone(), ProtoProjectConfigMessage {
             patterns: v.patterns.clone(),
             append_file_path: v.append_file_path.clone(),
+            remotes: Some(ProtoPatternList { patterns: v.remotes.clone() }),
+            priority: Some(v.priority),
+            exclude: Some(ProtoPatternList { patterns: v.exclude.clone() }),
         })).collect(),
         refresh_interval: config.refresh_interval,
         editor_uri_scheme: config.editor_uri_scheme.clone(),
@@ -75,27 +379,49 @@ fn to_proto_config(config: &Config) -> ProtoConfigMessage {
             ProtoTodoStateSet { states: state_set_vec.clone() }
         }).collect(),
         default_append_basename: config.default_append_basename.clone(),
+        auto_complete_parents: Some(config.auto_complete_parents),
+        enforce_dependencies: Some(config.enforce_dependencies),
+        views: Some(ProtoViewList { views: config.views.iter().map(to_proto_view_config).collect() }),
+        category_order: Some(ProtoCategoryOrderMessage { sort: config.category_order.sort.clone(), pinned: config.category_order.pinned.clone() }),
+        git: Some(ProtoGitCategoryMessage { show_branch: config.git.show_branch, show_worktree: config.git.show_worktree, group_submodules: config.git.group_submodules, group_by_remote: config.git.group_by_remote }),
+        packages: Some(ProtoPackageDiscoveryMessage { enabled: config.packages.enabled, manifests: config.packages.manifests.clone() }),
+        multi_project_membership: Some(config.multi_project_membership),
     }
 }
 
 // Maps ProtoConfigMessage to a single profile's Config (for config_models.rs)
-fn from_proto_config(proto_config: ProtoConfigMessage) -> Config {
+// Optional settings left unset by the client (e.g. one that predates them) keep their values from `current_config`
+fn from_proto_config(proto_config: ProtoConfigMessage, current_config: &Config) -> Config {
     Config {
         rg: RgConfig {
             paths: proto_config.rg.as_ref().map_or_else(Vec::new, |rg| rg.paths.clone()),
             ignore: proto_config.rg.as_ref().and_then(|rg| if rg.ignore.is_empty() { None } else { Some(rg.ignore.clone()) }),
             file_types: proto_config.rg.as_ref().and_then(|rg| if rg.file_types.is_empty() { None } else { Some(rg.file_types.clone()) }),
         },
-        projects: proto_config.projects.into_iter().map(|(k, v)| (k, ModelProjectConfig {
-            patterns: v.patterns.clone(),
-            append_file_path: v.append_file_path.clone(),
-        })).collect(),
+        projects: proto_config.projects.into_iter().map(|(k, v)| {
+            // Fields older clients don't send keep the project's current values
+            let current = current_config.projects.get(&k).cloned().unwrap_or_default();
+            (k, ModelProjectConfig {
+                patterns: v.patterns,
+                append_file_path: v.append_file_path,
+                remotes: v.remotes.map_or(current.remotes, |list| list.patterns),
+                priority: v.priority.unwrap_or(current.priority),
+                exclude: v.exclude.map_or(current.exclude, |list| list.patterns),
+            })
+        }).collect(),
         refresh_interval: proto_config.refresh_interval,
         editor_uri_scheme: proto_config.editor_uri_scheme,
         todo_states: proto_config.todo_states.into_iter().map(|proto_state_set| {
             proto_state_set.states // This is already Vec<String>
         }).collect(),
         default_append_basename: proto_config.default_append_basename,
+        auto_complete_parents: proto_config.auto_complete_parents.unwrap_or(current_config.auto_complete_parents),
+        enforce_dependencies: proto_config.enforce_dependencies.unwrap_or(current_config.enforce_dependencies),
+        views: proto_config.views.map_or_else(|| current_config.views.clone(), |list| list.views.into_iter().map(from_proto_view_config).collect()),
+        category_order: proto_config.category_order.map_or_else(|| current_config.category_order.clone(), |order| CategoryOrderConfig { sort: order.sort, pinned: order.pinned }),
+        git: proto_config.git.map_or_else(|| current_config.git.clone(), |git| GitCategoryConfig { show_branch: git.show_branch, show_worktree: git.show_worktree, group_submodules: git.group_submodules, group_by_remote: git.group_by_remote }),
+        packages: proto_config.packages.map_or_else(|| current_config.packages.clone(), |packages| PackageDiscoveryConfig { enabled: packages.enabled, manifests: packages.manifests }),
+        multi_project_membership: proto_config.multi_project_membership.unwrap_or(current_config.multi_project_membership),
     }
 }
 
@@ -108,13 +434,28 @@ pub struct MyTodoService {
 
 #[tonic::async_trait]
 impl TodoService for MyTodoService {
-    async fn get_todos(&self, _request: Request<GetTodosRequest>) -> Result<Response<GetTodosResponse>, Status> {
+    async fn get_todos(&self, request: Request<GetTodosRequest>) -> Result<Response<GetTodosResponse>, Status> {
+        let payload = request.into_inner();
+        let sort_keys = parse_sort_spec(&payload.sort).map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let group_by = parse_group_by(&payload.group_by).map_err(|e| Status::invalid_argument(e.to_string()))?;
         let app_config_guard = self.config_state.read().await;
         if let Some(active_config) = app_config_guard.get_active_config() {
             match find_and_process_todos(active_config, false) { // Pass active_config
-                Ok(processed_data) => {
-                    let proto_categories = processed_data.categories.iter().map(to_proto_todo_category).collect();
-                    Ok(Response::new(GetTodosResponse { categories: proto_categories }))
+                Ok(mut processed_data) => {
+                    record_scan_changes(active_config, &mut processed_data); // Only refreshing the todo list records history
+                    if active_config.enforce_dependencies { remember_scan_dependencies(&processed_data, active_config); }
+                    let scan_categories = std::mem::take(&mut processed_data.categories);
+                    let mut group_context = GroupContext::new(active_config, &scan_categories, "All", chrono::Local::now().naive_local());
+                    processed_data.categories = group_context.regroup_categories(scan_categories, group_by);
+                    if !payload.include_deferred { remove_deferred_todos(&mut processed_data.categories); }
+                    sort_category_todos(&mut processed_data.categories, &sort_keys);
+                    order_categories(&mut processed_data.categories, &active_config.category_order);
+                    let proto_categories = match payload.shape() {
+                        GetTodosShape::Tree => processed_data.categories.iter().map(to_proto_todo_category_tree).collect(),
+                        GetTodosShape::Flat => processed_data.categories.iter().map(to_proto_todo_category).collect(),
+                    };
+                    let proto_diagnostics = processed_data.diagnostics.iter().map(to_proto_scan_diagnostic).collect();
+                    Ok(Response::new(GetTodosResponse { categories: proto_categories, diagnostics: proto_diagnostics }))
                 }
                 Err(e) => Err(Status::internal(format!("Failed to process todos: {}", e))),
             }
@@ -147,8 +488,16 @@ impl TodoService for MyTodoService {
         let payload = request.into_inner();
         let app_config_guard = self.config_state.read().await;
         if let Some(active_config) = app_config_guard.get_active_config() {
-            match add_todo_to_file_grpc(active_config, &payload.category_type, &payload.category_name, &payload.content, payload.example_item_location.as_deref()) {
-                Ok(()) => Ok(Response::new(AddTodoResponse { status: "success".to_string(), message: "Todo added successfully".to_string() })),
+            match add_todo_to_file_grpc(active_config, payload.category_key.as_deref(), &payload.category_type, &payload.category_name, &payload.content, payload.example_item_location.as_deref(), !payload.skip_date_parsing) {
+                Ok(natural_due) => Ok(Response::new(AddTodoResponse {
+                    status: "success".to_string(),
+                    message: "Todo added successfully".to_string(),
+                    parsed_due: natural_due.map(|parsed| ProtoParsedDue {
+                        timestamp: parse_field_date(&parsed.due).as_ref().and_then(field_date_to_timestamp),
+                        phrase: parsed.phrase,
+                        due: parsed.due,
+                    }),
+                })),
                 Err(e) => { 
                     let (code, msg) = match e.kind() {
                         io::ErrorKind::NotFound => (tonic::Code::NotFound, e.to_string()),
@@ -166,9 +515,12 @@ impl TodoService for MyTodoService {
         let payload = request.into_inner();
         let app_config_guard = self.config_state.read().await;
         if let Some(active_config) = app_config_guard.get_active_config() {
+            ensure_prerequisites_closed(active_config, &payload.location, |_| true)?;
             match mark_todo_as_done_in_file_grpc(active_config, &payload.location, &payload.original_content) {
-                Ok((new_content, completed_status_changed)) => Ok(Response::new(MarkDoneResponse {
-                    status: "success".to_string(), message: "Todo marked as done".to_string(), new_content, completed: completed_status_changed,
+                Ok((new_content, completed_status_changed, updated_parents, spawned)) => Ok(Response::new(MarkDoneResponse {
+                    status: "success".to_string(), message: "Todo marked as done".to_string(), new_content, completed: completed_status_changed, updated_parents,
+                    spawned_location: spawned.as_ref().map(|(location, _)| location.clone()),
+                    spawned_content: spawned.map(|(_, content)| content),
                 })),
                 Err(e) => { 
                      let (code, msg) = match e.kind() {
@@ -189,12 +541,16 @@ impl TodoService for MyTodoService {
         let app_config_guard = self.config_state.read().await;
         if let Some(active_config) = app_config_guard.get_active_config() {
             let direction_as_i32 = payload.direction as i32;
+            ensure_prerequisites_closed(active_config, &payload.location, |marker| {
+                active_config.cycled_state_of(marker, direction_as_i32 == 0) == Some(TodoState::Done)
+            })?;
             match cycle_todo_state_in_file_grpc(active_config, &payload.location, &payload.original_content, direction_as_i32) {
-                Ok((new_content_part, new_marker)) => Ok(Response::new(CycleTodoStateResponse {
+                Ok((new_content_part, new_marker, updated_parents)) => Ok(Response::new(CycleTodoStateResponse {
                     status: "success".to_string(),
                     message: "Todo state cycled successfully".to_string(),
                     new_content: new_content_part,
                     new_marker,
+                    updated_parents,
                 })),
                 Err(e) => { 
                      let (code, msg) = match e.kind() {
@@ -212,6 +568,287 @@ impl TodoService for MyTodoService {
             Err(Status::not_found("Active profile configuration not found for cycle_todo_state.")) 
         }
     }
+
+    async fn update_todo_fields(&self, request: Request<UpdateTodoFieldsRequest>) -> Result<Response<UpdateTodoFieldsResponse>, Status> {
+        let payload = request.into_inner();
+        let app_config_guard = self.config_state.read().await;
+        if let Some(active_config) = app_config_guard.get_active_config() {
+            let mut updates: Vec<(String, Option<String>)> = payload.set.into_iter().map(|(name, value)| (name, Some(value))).collect();
+            updates.sort();
+            updates.extend(payload.remove.into_iter().map(|name| (name, None)));
+            match update_todo_fields_in_file_grpc(active_config, &payload.location, &payload.original_content, &updates) {
+                Ok(new_content) => Ok(Response::new(UpdateTodoFieldsResponse { status: "success".to_string(), message: "Todo fields updated".to_string(), new_content })),
+                Err(e) => {
+                    let (code, msg) = match e.kind() {
+                        io::ErrorKind::NotFound => (tonic::Code::NotFound, e.to_string()),
+                        io::ErrorKind::InvalidInput => (tonic::Code::InvalidArgument, e.to_string()),
+                        io::ErrorKind::PermissionDenied => (tonic::Code::PermissionDenied, e.to_string()),
+                        io::ErrorKind::Other if e.to_string().contains("Content modified") => (tonic::Code::Aborted, e.to_string()),
+                        _ => (tonic::Code::Internal, format!("Failed to update todo fields: {}", e)),
+                    };
+                    Err(Status::new(code, msg))
+                }
+            }
+        } else { Err(Status::not_found("Active profile configuration not found for update_todo_fields.")) }
+    }
+
+    async fn snooze(&self, request: Request<SnoozeRequest>) -> Result<Response<SnoozeResponse>, Status> {
+        let payload = request.into_inner();
+        let until = payload.until.trim();
+        let defer = if until.is_empty() {
+            None
+        } else if parse_field_date(until).is_some() {
+            Some(until.to_string())
+        } else {
+            match parse_natural_date(until, &chrono::Local::now().naive_local()) {
+                Some(date) => Some(date),
+                None => return Err(Status::invalid_argument(format!("Unrecognised snooze date: {}", until))),
+            }
+        };
+        let app_config_guard = self.config_state.read().await;
+        if let Some(active_config) = app_config_guard.get_active_config() {
+            let updates = vec![("defer".to_string(), defer.clone())];
+            match update_todo_fields_in_file_grpc(active_config, &payload.location, &payload.original_content, &updates) {
+                Ok(new_content) => Ok(Response::new(SnoozeResponse {
+                    status: "success".to_string(),
+                    message: if defer.is_some() { "Todo snoozed".to_string() } else { "Todo unsnoozed".to_string() },
+                    new_content,
+                    defer: defer.unwrap_or_default(),
+                })),
+                Err(e) => Err(io_error_status(e, "snooze todo")),
+            }
+        } else { Err(Status::not_found("Active profile configuration not found for snooze.")) }
+    }
+
+    async fn start_timer(&self, request: Request<StartTimerRequest>) -> Result<Response<StartTimerResponse>, Status> {
+        let payload = request.into_inner();
+        let app_config_guard = self.config_state.read().await;
+        if let Some(active_config) = app_config_guard.get_active_config() {
+            let processed_data = find_and_process_todos(active_config, false).map_err(|e| Status::internal(format!("Failed to process todos: {}", e)))?;
+            let (todo, category) = find_todo_by_id(&processed_data, &payload.todo_id)
+                .ok_or_else(|| Status::not_found(format!("No todo found with id {}", payload.todo_id)))?;
+            if todo.timer_started.is_some() { return Err(Status::already_exists(format!("A timer is already running for {}", payload.todo_id))); }
+
+            // Todos whose marker is in a 4-state set move to its active marker; others keep theirs
+            let moves_to_active = active_config.cycled_state_of(&todo.status, true).is_some() && active_config.todo_state_of(&todo.status) != Some(TodoState::Active);
+            let (new_content, new_marker) = if moves_to_active {
+                match set_todo_state_in_file_grpc(active_config, &todo.location, &todo.content, TodoState::Active) {
+                    Ok((new_content, new_marker, _)) => (new_content, new_marker),
+                    Err(e) => {
+                        let (code, msg) = match e.kind() {
+                            io::ErrorKind::NotFound => (tonic::Code::NotFound, e.to_string()),
+                            io::ErrorKind::InvalidInput => (tonic::Code::InvalidArgument, e.to_string()),
+                            io::ErrorKind::PermissionDenied => (tonic::Code::PermissionDenied, e.to_string()),
+                            io::ErrorKind::Other if e.to_string().contains("Content modified") => (tonic::Code::Aborted, e.to_string()),
+                            _ => (tonic::Code::Internal, format!("Failed to start timer: {}", e)),
+                        };
+                        return Err(Status::new(code, msg));
+                    }
+                }
+            } else { (todo.content.clone(), todo.status.clone()) };
+
+            match start_timer(&payload.todo_id, &new_content, category, chrono::Local::now().timestamp()) {
+                Ok(interval) => Ok(Response::new(StartTimerResponse {
+                    status: "success".to_string(),
+                    message: "Timer started".to_string(),
+                    location: todo.location.clone(),
+                    new_content,
+                    new_marker,
+                    started_at: interval.start,
+                })),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Err(Status::already_exists(e.to_string())),
+                Err(e) => Err(Status::internal(format!("Failed to write the time log: {}", e))),
+            }
+        } else { Err(Status::not_found("Active profile configuration not found for start_timer.")) }
+    }
+
+    async fn stop_timer(&self, request: Request<StopTimerRequest>) -> Result<Response<StopTimerResponse>, Status> {
+        let payload = request.into_inner();
+        let now = chrono::Local::now().timestamp();
+        match stop_timer(&payload.todo_id, now) {
+            Ok(interval) => {
+                let total_seconds = load_time_log()
+                    .map(|log| time_spent_by_todo(&log, now).get(&payload.todo_id).map_or(0, |spent| spent.seconds))
+                    .map_err(|e| Status::internal(format!("Failed to read the time log: {}", e)))?;
+                Ok(Response::new(StopTimerResponse {
+                    status: "success".to_string(),
+                    message: "Timer stopped".to_string(),
+                    elapsed_seconds: (interval.end.unwrap_or(now) - interval.start).max(0) as u64,
+                    total_seconds,
+                }))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Err(Status::failed_precondition(e.to_string())),
+            Err(e) => Err(Status::internal(format!("Failed to stop timer: {}", e))),
+        }
+    }
+
+    async fn get_time_report(&self, request: Request<GetTimeReportRequest>) -> Result<Response<GetTimeReportResponse>, Status> {
+        let payload = request.into_inner();
+        let app_config_guard = self.config_state.read().await;
+        if let Some(active_config) = app_config_guard.get_active_config() {
+            let processed_data = find_and_process_todos(active_config, false).map_err(|e| Status::internal(format!("Failed to process todos: {}", e)))?;
+            let time_log = load_time_log().map_err(|e| Status::internal(format!("Failed to read the time log: {}", e)))?;
+            let report = build_time_report(&time_log, &processed_data, payload.since, payload.until, chrono::Local::now().timestamp());
+            Ok(Response::new(GetTimeReportResponse {
+                by_todo: report.by_todo.iter().map(to_proto_time_total).collect(),
+                by_project: report.by_project.iter().map(to_proto_time_total).collect(),
+                by_day: report.by_day.iter().map(to_proto_time_total).collect(),
+                total_seconds: report.total_seconds,
+            }))
+        } else { Err(Status::not_found("Active profile configuration not found for get_time_report.")) }
+    }
+
+    async fn get_history(&self, request: Request<GetHistoryRequest>) -> Result<Response<GetHistoryResponse>, Status> {
+        let payload = request.into_inner();
+        let filter = HistoryFilter {
+            kinds: payload.kinds().map(from_proto_history_event_kind).collect(),
+            todo_id: payload.todo_id,
+            file: payload.file,
+            project: payload.project,
+            since: payload.since,
+            until: payload.until,
+        };
+        match read_history(&filter) {
+            Ok(events) => Ok(Response::new(GetHistoryResponse { events: events.iter().map(to_proto_history_event).collect() })),
+            Err(e) => Err(Status::internal(format!("Failed to read history: {}", e))),
+        }
+    }
+
+    async fn get_disappeared_todos(&self, _request: Request<GetDisappearedTodosRequest>) -> Result<Response<GetDisappearedTodosResponse>, Status> {
+        match list_disappeared_todos() {
+            Ok(todos) => Ok(Response::new(GetDisappearedTodosResponse { todos: todos.iter().map(to_proto_disappeared_todo).collect() })),
+            Err(e) => Err(Status::internal(format!("Failed to read disappeared todos: {}", e))),
+        }
+    }
+
+    async fn resolve_disappeared_todo(&self, request: Request<ResolveDisappearedTodoRequest>) -> Result<Response<ResolveDisappearedTodoResponse>, Status> {
+        let payload = request.into_inner();
+        let app_config_guard = self.config_state.read().await;
+        if let Some(active_config) = app_config_guard.get_active_config() {
+            let result = match payload.action() {
+                ResolveDisappearedAction::Restore => restore_disappeared_todo(active_config, &payload.key)
+                    .map(|location| ("Todo restored".to_string(), location)),
+                ResolveDisappearedAction::ConfirmDone => confirm_disappeared_todo_done(&payload.key)
+                    .map(|_| ("Todo recorded as completed".to_string(), String::new())),
+            };
+            match result {
+                Ok((message, location)) => Ok(Response::new(ResolveDisappearedTodoResponse { status: "success".to_string(), message, location })),
+                Err(e) => {
+                    let (code, msg) = match e.kind() {
+                        io::ErrorKind::NotFound => (tonic::Code::NotFound, e.to_string()),
+                        io::ErrorKind::PermissionDenied => (tonic::Code::PermissionDenied, e.to_string()),
+                        _ => (tonic::Code::Internal, format!("Failed to resolve disappeared todo: {}", e)),
+                    };
+                    Err(Status::new(code, msg))
+                }
+            }
+        } else { Err(Status::not_found("Active profile configuration not found for resolve_disappeared_todo.")) }
+    }
+
+    async fn get_agenda(&self, request: Request<GetAgendaRequest>) -> Result<Response<GetAgendaResponse>, Status> {
+        let payload = request.into_inner();
+        if payload.days > MAX_AGENDA_DAYS {
+            return Err(Status::invalid_argument(format!("days must be at most {}", MAX_AGENDA_DAYS)));
+        }
+        let days = if payload.days == 0 { DEFAULT_AGENDA_DAYS } else { payload.days };
+        let app_config_guard = self.config_state.read().await;
+        if let Some(active_config) = app_config_guard.get_active_config() {
+            match find_and_process_todos(active_config, false) {
+                Ok(processed_data) => {
+                    let agenda = build_agenda(&processed_data, active_config, days, &chrono::Local::now().naive_local());
+                    Ok(Response::new(GetAgendaResponse {
+                        overdue: agenda.overdue.iter().map(to_proto_agenda_entry).collect(),
+                        today: agenda.today.iter().map(to_proto_agenda_entry).collect(),
+                        upcoming: agenda.upcoming.iter().map(|day| ProtoAgendaDay {
+                            date: day.date.clone(),
+                            entries: day.entries.iter().map(to_proto_agenda_entry).collect(),
+                        }).collect(),
+                        undated: agenda.undated.iter().map(to_proto_agenda_entry).collect(),
+                    }))
+                }
+                Err(e) => Err(Status::internal(format!("Failed to process todos: {}", e))),
+            }
+        } else { Err(Status::not_found("Active profile configuration not found for get_agenda.")) }
+    }
+
+    async fn query_todos(&self, request: Request<QueryTodosRequest>) -> Result<Response<QueryTodosResponse>, Status> {
+        let payload = request.into_inner();
+        let query = parse_query(&payload.query, &chrono::Local::now().naive_local()).map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let sort_keys = parse_sort_spec(&payload.sort).map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let app_config_guard = self.config_state.read().await;
+        if let Some(active_config) = app_config_guard.get_active_config() {
+            match find_and_process_todos(active_config, false) {
+                Ok(mut processed_data) => {
+                    if !payload.include_deferred && !query.asks_for_deferred() { remove_deferred_todos(&mut processed_data.categories); }
+                    let mut matches = query_todos(&processed_data, active_config, &query);
+                    sort_query_matches(&mut matches, &sort_keys);
+                    Ok(Response::new(QueryTodosResponse { matches: matches.iter().map(to_proto_query_match).collect() }))
+                }
+                Err(e) => Err(Status::internal(format!("Failed to process todos: {}", e))),
+            }
+        } else { Err(Status::not_found("Active profile configuration not found for query_todos.")) }
+    }
+
+    async fn get_view(&self, request: Request<GetViewRequest>) -> Result<Response<GetViewResponse>, Status> {
+        let payload = request.into_inner();
+        let app_config_guard = self.config_state.read().await;
+        if let Some(active_config) = app_config_guard.get_active_config() {
+            let view = find_view(active_config, &payload.name).ok_or_else(|| Status::not_found(format!("View '{}' not found.", payload.name)))?;
+            match find_and_process_todos(active_config, false) {
+                Ok(processed_data) => match evaluate_view(&processed_data, active_config, view, payload.include_deferred, &chrono::Local::now().naive_local()) {
+                    Ok(groups) => Ok(Response::new(GetViewResponse {
+                        view: Some(to_proto_view_config(view)),
+                        groups: groups.iter().map(to_proto_view_group).collect(),
+                    })),
+                    Err(e) => Err(Status::invalid_argument(e.to_string())),
+                },
+                Err(e) => Err(Status::internal(format!("Failed to process todos: {}", e))),
+            }
+        } else { Err(Status::not_found("Active profile configuration not found for get_view.")) }
+    }
+
+    async fn migrate_format(&self, request: Request<MigrateFormatRequest>) -> Result<Response<MigrateFormatResponse>, Status> {
+        let payload = request.into_inner();
+        let app_config_guard = self.config_state.read().await;
+        if let Some(active_config) = app_config_guard.get_active_config() {
+            match migrate_todo_format(active_config, payload.dry_run) {
+                Ok(report) => Ok(Response::new(MigrateFormatResponse {
+                    dry_run: report.dry_run,
+                    files: report.files.iter().map(to_proto_migrated_file).collect(),
+                    todos_scanned: report.todos_scanned,
+                    todos_migrated: report.todos_migrated,
+                    todos_skipped: report.todos_skipped,
+                    ids_assigned: report.ids_assigned,
+                    timestamps_decoded: report.timestamps_decoded,
+                    files_changed: report.files_changed,
+                    files_failed: report.files_failed,
+                })),
+                Err(e) => Err(Status::internal(format!("Failed to migrate todo format: {}", e))),
+            }
+        } else { Err(Status::not_found("Active profile configuration not found for migrate_format.")) }
+    }
+
+    async fn get_dependency_graph(&self, _request: Request<GetDependencyGraphRequest>) -> Result<Response<GetDependencyGraphResponse>, Status> {
+        let app_config_guard = self.config_state.read().await;
+        if let Some(active_config) = app_config_guard.get_active_config() {
+            match find_and_process_todos(active_config, false) {
+                Ok(processed_data) => {
+                    remember_scan_dependencies(&processed_data, active_config);
+                    let graph = build_dependency_graph(&processed_data, active_config);
+                    Ok(Response::new(GetDependencyGraphResponse {
+                        nodes: graph.nodes.iter().map(to_proto_dependency_node).collect(),
+                        edges: graph.edges.iter().map(|e| ProtoDependencyEdge { from: e.from.clone(), to: e.to.clone() }).collect(),
+                        cycles: graph.cycles.into_iter().map(|keys| ProtoDependencyCycle { keys }).collect(),
+                        dangling: graph.dangling.iter().map(|d| ProtoDanglingReference { from: d.from.clone(), reference: d.reference.clone() }).collect(),
+                        unblocked: graph.unblocked,
+                    }))
+                }
+                Err(e) => Err(Status::internal(format!("Failed to process todos: {}", e))),
+            }
+        } else {
+            Err(Status::not_found("Active profile configuration not found for get_dependency_graph."))
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -245,10 +882,10 @@ impl ConfigService for MyConfigService {
     async fn update_config(&self, request: Request<UpdateConfigRequest>) -> Result<Response<UpdateConfigResponse>, Status> {
         let proto_profile_config_to_save = request.into_inner().config
             .ok_or_else(|| Status::invalid_argument("Config message for profile is missing"))?;
-        let new_profile_config = from_proto_config(proto_profile_config_to_save);
-
         let mut app_config_guard = self.config_state.write().await;
         let active_profile_name = app_config_guard.active_profile.clone();
+        let current_profile_config = app_config_guard.profiles.get(&active_profile_name).cloned().unwrap_or_default();
+        let new_profile_config = from_proto_config(proto_profile_config_to_save, &current_profile_config);
         app_config_guard.profiles.insert(active_profile_name.clone(), new_profile_config);
         
         let app_config_to_write = app_config_guard.clone();
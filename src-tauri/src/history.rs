@@ -0,0 +1,136 @@
+#![allow(clippy::all)]
+use crate::config_io::get_data_file_path;
+use crate::config_models::Config;
+use crate::scan_changes::note_recorded_events;
+use crate::todo_models::{HistoryEvent, HistoryEventKind, TodoState};
+use crate::todo_parsing::parse_todo_identity;
+use crate::todo_processing::category_name_for_path;
+use crate::utils::extract_cleaned_content_from_line;
+
+use chrono::Local;
+use lazy_static::lazy_static;
+use parking_lot::Mutex as ParkingMutex;
+use regex::Regex;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+// --- Activity history: an append-only JSON-lines log next to the config ---
+// File operations record what they changed; scans record what changed behind Unitodo's back (see scan_changes).
+
+pub const HISTORY_FILE_NAME: &str = "history.jsonl";
+
+#[derive(Debug, Clone, Default)]
+pub struct HistoryFilter {
+    pub todo_id: Option<String>,
+    pub file: Option<String>, // A file, or a directory containing the todo
+    pub project: Option<String>,
+    pub since: Option<i64>,   // Unix seconds, inclusive
+    pub until: Option<i64>,   // Unix seconds, exclusive
+    pub kinds: Vec<HistoryEventKind>, // Any kind if empty
+}
+
+impl HistoryFilter {
+    fn matches(&self, event: &HistoryEvent) -> bool {
+        let file = event.location.rsplit_once(':').map_or(event.location.as_str(), |(file, _)| file);
+        self.todo_id.as_ref().map_or(true, |id| event.todo_id.as_ref() == Some(id))
+            && self.file.as_ref().map_or(true, |f| file == f || Path::new(file).starts_with(f))
+            && self.project.as_ref().map_or(true, |p| &event.project == p)
+            && self.since.map_or(true, |since| event.at >= since)
+            && self.until.map_or(true, |until| event.at < until)
+            && (self.kinds.is_empty() || self.kinds.contains(&event.kind))
+    }
+}
+
+lazy_static! {
+    static ref HISTORY_FILE_MUTEX: ParkingMutex<()> = ParkingMutex::new(());
+}
+
+// The kind of event for a todo moving to `marker`
+pub fn state_event_kind(config: &Config, marker: &str) -> HistoryEventKind {
+    match config.todo_state_of(marker) {
+        Some(TodoState::Active) => HistoryEventKind::Started,
+        Some(TodoState::Done) => HistoryEventKind::Completed,
+        Some(TodoState::Cancelled) => HistoryEventKind::Cancelled,
+        _ => HistoryEventKind::Reopened,
+    }
+}
+
+// Builds the history events of one file operation. The todo pattern and the file's project are looked up once, before
+// the file is locked.
+pub struct FileEvents<'a> {
+    config: &'a Config,
+    pattern: String,
+    marker_re: Option<Regex>,
+    project: String,
+}
+
+impl<'a> FileEvents<'a> {
+    pub fn new(config: &'a Config, file: &str) -> Self {
+        let pattern = config.get_effective_rg_pattern();
+        let marker_re = Regex::new(&pattern).ok();
+        FileEvents { config, pattern, marker_re, project: category_name_for_path(Path::new(file), config) }
+    }
+
+    // An event for the todo on `line` at `location` (`file:line`), stamped now. None if the line holds no todo.
+    pub fn event(&self, kind: HistoryEventKind, location: &str, line: &str, previous: Option<String>) -> Option<HistoryEvent> {
+        let marker = self.marker_re.as_ref()?.find(line)?.as_str().to_string();
+        let content = extract_cleaned_content_from_line(line, &self.pattern).ok()?;
+        Some(HistoryEvent {
+            at: Local::now().timestamp(),
+            kind,
+            todo_id: parse_todo_identity(&content).id,
+            location: location.to_string(),
+            project: self.project.clone(),
+            status: marker,
+            content,
+            previous,
+        })
+    }
+
+    // An event for the todo on `line` having moved to its current marker
+    pub fn state_event(&self, location: &str, line: &str, previous: Option<String>) -> Option<HistoryEvent> {
+        let marker = self.marker_re.as_ref()?.find(line)?.as_str();
+        self.event(state_event_kind(self.config, marker), location, line, previous)
+    }
+}
+
+// Appends events to the history log. Failures are logged rather than returned, so a change that was already written
+// to a todo's file is never reported as failed.
+pub fn record_history(events: Vec<HistoryEvent>) {
+    if events.is_empty() { return; }
+    note_recorded_events(&events);
+    if let Err(e) = append_history(&events) {
+        log::warn!("Failed to write {} history event(s): {}", events.len(), e);
+    }
+}
+
+pub fn append_history(events: &[HistoryEvent]) -> io::Result<()> {
+    if events.is_empty() { return Ok(()); }
+    let path = get_data_file_path(HISTORY_FILE_NAME)?;
+    if let Some(parent) = path.parent() { fs::create_dir_all(parent)?; }
+    let mut lines = String::new();
+    for event in events {
+        let line = serde_json::to_string(event).map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Failed to serialize history event: {}", e)))?;
+        lines.push_str(&line);
+        lines.push('\n');
+    }
+    let _guard = HISTORY_FILE_MUTEX.lock();
+    OpenOptions::new().create(true).append(true).open(&path)?.write_all(lines.as_bytes())
+}
+
+// Events matching `filter`, oldest first
+pub fn read_history(filter: &HistoryFilter) -> io::Result<Vec<HistoryEvent>> {
+    let path = get_data_file_path(HISTORY_FILE_NAME)?;
+    let _guard = HISTORY_FILE_MUTEX.lock();
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut events: Vec<HistoryEvent> = contents.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()).filter_map(|(i, line)| {
+        serde_json::from_str(line).map_err(|e| log::warn!("Skipping unreadable history line {}: {}", i + 1, e)).ok()
+    }).filter(|event| filter.matches(event)).collect();
+    events.sort_by_key(|event| event.at);
+    Ok(events)
+}
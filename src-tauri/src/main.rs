@@ -7,14 +7,33 @@ pub mod unitodo {
 }
 
 // Declare other application modules
+mod agenda;
+mod cli;
 mod config_io;
 mod config_models;
+mod dependency_graph;
 mod file_operations;
+mod fingerprint;
+mod format_migration;
+mod git_repo;
+mod grouping;
 mod grpc_services;
+mod history;
+mod natural_dates;
+mod packages;
+mod project_matching;
+mod project_overrides;
+mod recurrence;
+mod scan_changes;
 mod tauri_commands;
+mod time_tracking;
 mod todo_models;
+mod todo_parsing;
 mod todo_processing;
+mod todo_query;
+mod todo_sort;
 mod utils;
+mod views;
 
 // Use statements for items directly used in this main.rs file
 use crate::config_io::load_config_from_file;
@@ -48,6 +67,11 @@ fn try_bind_port(port: u16) -> bool {
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
 
+    if let Some(command) = crate::cli::parse_cli_command() {
+        crate::cli::run_cli_command(command)?;
+        return Ok(());
+    }
+
     let mut found_port: Option<u16> = None;
     for port_candidate in 50051..=50100 {
         if try_bind_port(port_candidate) {
@@ -130,6 +154,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             crate::tauri_commands::add_todo_command,
             crate::tauri_commands::mark_done_command,
             crate::tauri_commands::cycle_todo_state_command,
+            crate::tauri_commands::get_dependency_graph_command,
+            crate::tauri_commands::update_todo_fields_command,
+            crate::tauri_commands::snooze_command,
+            crate::tauri_commands::start_timer_command,
+            crate::tauri_commands::stop_timer_command,
+            crate::tauri_commands::get_time_report_command,
+            crate::tauri_commands::get_history_command,
+            crate::tauri_commands::get_disappeared_todos_command,
+            crate::tauri_commands::resolve_disappeared_todo_command,
+            crate::tauri_commands::migrate_format_command,
+            crate::tauri_commands::get_agenda_command,
+            crate::tauri_commands::query_todos_command,
+            crate::tauri_commands::get_view_command,
             crate::tauri_commands::get_grpc_port_command,
             crate::tauri_commands::get_active_profile_command,
             crate::tauri_commands::set_active_profile_command,
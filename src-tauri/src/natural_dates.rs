@@ -0,0 +1,228 @@
+#![allow(clippy::all)]
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+
+// --- Natural-language due dates at the end of new todos ---
+// "0 fix login bug due fri" or "review PR tomorrow 3pm": the trailing date phrase is resolved against the local clock
+// and replaced by a normalised `@due(...)` field. Only a phrase at the very end of the content is read, so words like
+// "monday" in the middle of a sentence are left alone. A lone weekday, "today" or abbreviation at the end is read only
+// after due/by/on, so "the cat sat" stays text.
+
+const MAX_PHRASE_WORDS: usize = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NaturalDue {
+    pub phrase: String,    // The words that were read as the date, as typed
+    pub due: String,       // Normalised `@due` value: `YYYY-MM-DD` or `YYYY-MM-DD HH:MM`
+    pub remaining: String, // Content with the phrase removed
+}
+
+// Finds the longest trailing date phrase in `content`, keeping at least one word of text
+pub fn extract_natural_due(content: &str, now: &NaiveDateTime) -> Option<NaturalDue> {
+    let words: Vec<&str> = content.split_whitespace().collect();
+    for phrase_len in (1..=MAX_PHRASE_WORDS.min(words.len().saturating_sub(1))).rev() {
+        let split_at = words.len() - phrase_len;
+        let phrase_words: Vec<String> = words[split_at..].iter().map(|w| w.to_lowercase()).collect();
+        if is_ambiguous_day_word(&phrase_words) { continue; }
+        if let Some((date, time)) = parse_date_phrase(&phrase_words, now) {
+            return Some(NaturalDue { phrase: words[split_at..].join(" "), due: format_date_phrase(date, time), remaining: words[..split_at].join(" ") });
+        }
+    }
+    None
+}
+
+// A single word that also reads as plain text: a weekday, "today" or an abbreviation
+fn is_ambiguous_day_word(words: &[String]) -> bool {
+    match words {
+        [word] => matches!(word.as_str(), "today" | "tmr" | "tmrw") || parse_weekday(word).is_some(),
+        _ => false,
+    }
+}
+
+// Reads a whole date phrase ("tomorrow", "next mon 9am", "2025-06-01") as a normalised field date: `YYYY-MM-DD` or `YYYY-MM-DD HH:MM`
+pub fn parse_natural_date(text: &str, now: &NaiveDateTime) -> Option<String> {
+    let words: Vec<String> = text.split_whitespace().map(|w| w.to_lowercase()).collect();
+    parse_date_phrase(&words, now).map(|(date, time)| format_date_phrase(date, time))
+}
+
+fn format_date_phrase(date: NaiveDate, time: Option<NaiveTime>) -> String {
+    match time {
+        Some(t) => format!("{} {}", date.format("%Y-%m-%d"), t.format("%H:%M")),
+        None => date.format("%Y-%m-%d").to_string(),
+    }
+}
+
+// `[due|by|on] <day> [[at] <time>]` or `[due|by|on|at] <time>` (today), consuming every word
+fn parse_date_phrase(words: &[String], now: &NaiveDateTime) -> Option<(NaiveDate, Option<NaiveTime>)> {
+    let words = match words.first().map(|w| w.as_str()) {
+        Some("due") | Some("by") | Some("on") => &words[1..],
+        _ => words,
+    };
+    if words.is_empty() { return None; }
+
+    let today = now.date();
+    if let Some(time) = parse_time_words(words) { return Some((today, Some(time))); }
+    for day_len in (1..=words.len().min(3)).rev() {
+        let Some(date) = parse_day_words(&words[..day_len], today) else { continue };
+        let rest = &words[day_len..];
+        if rest.is_empty() { return Some((date, None)); }
+        if let Some(time) = parse_time_words(rest) { return Some((date, Some(time))); }
+    }
+    None
+}
+
+fn parse_day_words(words: &[String], today: NaiveDate) -> Option<NaiveDate> {
+    let w: Vec<&str> = words.iter().map(|s| s.as_str()).collect();
+    match w.as_slice() {
+        ["today"] => Some(today),
+        ["tomorrow"] | ["tmr"] | ["tmrw"] => Some(today + Duration::days(1)),
+        ["next", "week"] => Some(upcoming_weekday(today, Weekday::Mon)),
+        ["next", "month"] => NaiveDate::from_ymd_opt(today.year() + today.month() as i32 / 12, today.month() % 12 + 1, 1),
+        [day] if parse_weekday(day).is_some() => Some(upcoming_weekday(today, parse_weekday(day)?)),
+        ["next", day] => parse_weekday(day).map(|wd| upcoming_weekday(today, wd) + Duration::days(7)),
+        [date] => NaiveDate::parse_from_str(date, "%Y-%m-%d").ok(),
+        ["in", amount] => today.checked_add_signed(Duration::try_days(parse_offset(amount, None)?)?),
+        ["in", amount, unit] => today.checked_add_signed(Duration::try_days(parse_offset(amount, Some(unit))?)?),
+        _ => None,
+    }
+}
+
+// `3d`, `2w` or `3` followed by a unit word, in days; None if the number is too large
+fn parse_offset(amount: &str, unit: Option<&str>) -> Option<i64> {
+    let (number, unit) = match unit {
+        Some(unit) => (amount, unit),
+        None => amount.split_at(amount.find(|c: char| !c.is_ascii_digit())?),
+    };
+    let n: i64 = number.parse().ok()?;
+    match unit {
+        "d" | "day" | "days" => Some(n),
+        "w" | "week" | "weeks" => n.checked_mul(7),
+        _ => None,
+    }
+}
+
+pub fn parse_weekday(word: &str) -> Option<Weekday> {
+    match word {
+        "mon" | "monday" => Some(Weekday::Mon),
+        "tue" | "tues" | "tuesday" => Some(Weekday::Tue),
+        "wed" | "weds" | "wednesday" => Some(Weekday::Wed),
+        "thu" | "thur" | "thurs" | "thursday" => Some(Weekday::Thu),
+        "fri" | "friday" => Some(Weekday::Fri),
+        "sat" | "saturday" => Some(Weekday::Sat),
+        "sun" | "sunday" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+// The next `weekday` strictly after `today` (so "fri" on a Friday means a week later)
+pub fn upcoming_weekday(today: NaiveDate, weekday: Weekday) -> NaiveDate {
+    let days_ahead = (weekday.num_days_from_monday() as i64 - today.weekday().num_days_from_monday() as i64 + 7) % 7;
+    today + Duration::days(if days_ahead == 0 { 7 } else { days_ahead })
+}
+
+// `[at] 3pm`, `3:30pm`, `15:00` or `noon`
+fn parse_time_words(words: &[String]) -> Option<NaiveTime> {
+    let words = if words.first().map(|w| w.as_str()) == Some("at") { &words[1..] } else { words };
+    match words {
+        [word] => parse_time(word),
+        [word, meridiem] if meridiem == "am" || meridiem == "pm" => parse_time(&format!("{}{}", word, meridiem)),
+        _ => None,
+    }
+}
+
+fn parse_time(word: &str) -> Option<NaiveTime> {
+    if word == "noon" { return NaiveTime::from_hms_opt(12, 0, 0); }
+    if word == "midnight" { return NaiveTime::from_hms_opt(0, 0, 0); }
+    let (clock, meridiem) = if let Some(c) = word.strip_suffix("am") { (c, Some(false)) }
+        else if let Some(c) = word.strip_suffix("pm") { (c, Some(true)) }
+        else { (word, None) };
+    let (hour, minute): (u32, u32) = match clock.split_once(':') {
+        Some((h, m)) if m.len() == 2 => (h.parse().ok()?, m.parse().ok()?),
+        None if meridiem.is_some() => (clock.parse().ok()?, 0),
+        _ => return None, // A bare number is too ambiguous to be a time
+    };
+    let hour = match meridiem {
+        Some(_) if hour == 0 || hour > 12 => return None,
+        Some(true) => hour % 12 + 12,
+        Some(false) => hour % 12,
+        None => hour,
+    };
+    NaiveTime::from_hms_opt(hour, minute, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Friday 2024-05-10, 12:00
+    fn now() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 5, 10).unwrap().and_hms_opt(12, 0, 0).unwrap()
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn phrase(text: &str, now: &NaiveDateTime) -> Option<(NaiveDate, Option<NaiveTime>)> {
+        let words: Vec<String> = text.split_whitespace().map(str::to_string).collect();
+        parse_date_phrase(&words, now)
+    }
+
+    #[test]
+    fn parses_day_phrases() {
+        assert_eq!(phrase("tomorrow", &now()), Some((date(2024, 5, 11), None)));
+        assert_eq!(phrase("due mon", &now()), Some((date(2024, 5, 13), None)));
+        assert_eq!(phrase("in 3 days", &now()), Some((date(2024, 5, 13), None)));
+        assert_eq!(phrase("in 2w", &now()), Some((date(2024, 5, 24), None)));
+        assert_eq!(phrase("next week", &now()), Some((date(2024, 5, 13), None)));
+        assert_eq!(phrase("2025-06-01", &now()), Some((date(2025, 6, 1), None)));
+        assert_eq!(phrase("someday", &now()), None);
+    }
+
+    #[test]
+    fn weekdays_roll_over_to_the_next_week() {
+        assert_eq!(phrase("fri", &now()), Some((date(2024, 5, 17), None))); // Today is a Friday
+        assert_eq!(phrase("next fri", &now()), Some((date(2024, 5, 24), None)));
+        assert_eq!(phrase("thursday", &now()), Some((date(2024, 5, 16), None)));
+    }
+
+    #[test]
+    fn next_month_crosses_the_year() {
+        assert_eq!(phrase("next month", &now()), Some((date(2024, 6, 1), None)));
+        let december = date(2024, 12, 15).and_hms_opt(9, 0, 0).unwrap();
+        assert_eq!(phrase("next month", &december), Some((date(2025, 1, 1), None)));
+    }
+
+    #[test]
+    fn parses_times() {
+        let at = |h, m| NaiveTime::from_hms_opt(h, m, 0);
+        assert_eq!(phrase("3pm", &now()), Some((date(2024, 5, 10), at(15, 0))));
+        assert_eq!(phrase("by noon", &now()), Some((date(2024, 5, 10), at(12, 0))));
+        assert_eq!(phrase("tomorrow at 9:30am", &now()), Some((date(2024, 5, 11), at(9, 30))));
+        assert_eq!(phrase("fri 15:00", &now()), Some((date(2024, 5, 17), at(15, 0))));
+        assert_eq!(phrase("13pm", &now()), None);
+        assert_eq!(phrase("15", &now()), None); // A bare number isn't a time
+    }
+
+    #[test]
+    fn extracts_a_trailing_phrase() {
+        let due = extract_natural_due("0 fix login bug due fri", &now()).unwrap();
+        assert_eq!((due.phrase.as_str(), due.due.as_str(), due.remaining.as_str()), ("due fri", "2024-05-17", "0 fix login bug"));
+        let due = extract_natural_due("review PR tomorrow 3pm", &now()).unwrap();
+        assert_eq!((due.due.as_str(), due.remaining.as_str()), ("2024-05-11 15:00", "review PR"));
+        assert_eq!(extract_natural_due("0 ship by today", &now()).map(|d| d.due), Some("2024-05-10".to_string()));
+        assert_eq!(extract_natural_due("0 meet on Sat", &now()).map(|d| d.due), Some("2024-05-11".to_string()));
+    }
+
+    #[test]
+    fn keeps_at_least_one_word() {
+        assert_eq!(extract_natural_due("tomorrow", &now()), None);
+        assert_eq!(extract_natural_due("due tomorrow", &now()).map(|d| d.remaining), Some("due".to_string()));
+    }
+
+    #[test]
+    fn leaves_sentence_words_alone() {
+        for content in ["0 the cat sat", "0 ship what we have today", "0 finish tod", "0 call Monday", "0 nap tmrw", "0 monday standup notes"] {
+            assert_eq!(extract_natural_due(content, &now()), None, "{}", content);
+        }
+    }
+}
@@ -0,0 +1,91 @@
+#![allow(clippy::all)]
+use crate::config_models::PackageDiscoveryConfig;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// --- Package discovery from manifest files ---
+// With discovery on, a todo inside a git repository belongs to the nearest package above it, found by walking up
+// from its file to the repository root. A package is a directory whose manifest names one: a Cargo crate, an npm
+// package, a Python project or a Go module. Workspace roots that only list members (a Cargo `[workspace]` without
+// `[package]`, a package.json with `workspaces`) aren't packages, and neither is a package at the repository root,
+// so its todos stay in the repository's category.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ManifestKind {
+    Cargo,
+    Npm,
+    Python,
+    Go,
+}
+
+// (kind, file name, name in `manifests`), in the order a directory's manifests are tried
+const MANIFESTS: [(ManifestKind, &str, &str); 4] = [
+    (ManifestKind::Cargo, "Cargo.toml", "cargo"),
+    (ManifestKind::Npm, "package.json", "npm"),
+    (ManifestKind::Python, "pyproject.toml", "python"),
+    (ManifestKind::Go, "go.mod", "go"),
+];
+
+// Logged once per scan rather than for every directory looked at
+pub fn warn_unknown_manifests(config: &PackageDiscoveryConfig) {
+    for name in &config.manifests {
+        if !MANIFESTS.iter().any(|(_, _, known)| known.eq_ignore_ascii_case(name)) {
+            log::warn!("Unknown package manifest '{}' (expected cargo, npm, python or go); ignoring it", name);
+        }
+    }
+}
+
+fn enabled_manifests(config: &PackageDiscoveryConfig) -> Vec<(ManifestKind, &'static str)> {
+    MANIFESTS.iter()
+        .filter(|(_, _, name)| config.manifests.is_empty() || config.manifests.iter().any(|m| m.eq_ignore_ascii_case(name)))
+        .map(|(kind, file_name, _)| (*kind, *file_name))
+        .collect()
+}
+
+// The package a manifest declares, or None when it declares none (or can't be read)
+fn read_package_name(kind: ManifestKind, manifest_path: &Path) -> Option<String> {
+    let text = fs::read_to_string(manifest_path).ok()?;
+    let name = match kind {
+        ManifestKind::Cargo => {
+            let manifest: toml::Value = toml::from_str(&text).ok()?;
+            manifest.get("package")?.get("name")?.as_str()?.to_string()
+        }
+        ManifestKind::Npm => {
+            let manifest: serde_json::Value = serde_json::from_str(&text).ok()?;
+            if manifest.get("workspaces").is_some() { return None; }
+            manifest.get("name")?.as_str()?.to_string()
+        }
+        ManifestKind::Python => {
+            let manifest: toml::Value = toml::from_str(&text).ok()?;
+            manifest.get("project").and_then(|p| p.get("name"))
+                .or_else(|| manifest.get("tool")?.get("poetry")?.get("name"))?
+                .as_str()?.to_string()
+        }
+        ManifestKind::Go => {
+            let module = text.lines().find_map(|line| line.trim().strip_prefix("module "))?.trim().trim_matches('"');
+            // The last path element, skipping a major-version suffix: `github.com/acme/api/v2` is `api`
+            let mut elements = module.rsplit('/');
+            let last = elements.next()?;
+            let is_major_version = last.len() > 1 && last.starts_with('v') && last[1..].chars().all(|c| c.is_ascii_digit());
+            if is_major_version { elements.next().unwrap_or(last).to_string() } else { last.to_string() }
+        }
+    };
+    if name.trim().is_empty() { None } else { Some(name) }
+}
+
+// The package declared in `dir` itself, trying its manifests in order
+pub fn package_in_dir(dir: &Path, config: &PackageDiscoveryConfig) -> Option<String> {
+    enabled_manifests(config).into_iter().find_map(|(kind, file_name)| read_package_name(kind, &dir.join(file_name)))
+}
+
+// The canonical directory of the nearest package above `file_path` below the (canonical) `repo_root`
+pub fn nearest_package_dir(file_path: &Path, repo_root: &Path, config: &PackageDiscoveryConfig) -> Option<PathBuf> {
+    if !config.enabled { return None; }
+    let mut dir = file_path.parent()?.canonicalize().ok()?;
+    while dir.starts_with(repo_root) && dir != repo_root {
+        if package_in_dir(&dir, config).is_some() { return Some(dir); }
+        dir = dir.parent()?.to_path_buf();
+    }
+    None
+}
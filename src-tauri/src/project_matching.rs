@@ -0,0 +1,80 @@
+#![allow(clippy::all)]
+use crate::config_models::{Config, ProjectConfig};
+use crate::git_repo::normalise_remote_url;
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::path::Path;
+
+// --- Project matching ---
+// Projects are tried by descending `priority`, then by name, so a file matched by several projects always lands in
+// the same one. A project matches a file when one of its path patterns, or one of its remote patterns against the
+// file's repository, matches and none of its `exclude` patterns matches the file's path. All patterns are compiled
+// once per scan.
+
+#[derive(Debug)]
+pub struct ProjectMatcher {
+    projects: Vec<String>, // Names, in matching order
+    paths: GlobSet,
+    path_owners: Vec<usize>, // Pattern index -> project index, for each set
+    excludes: GlobSet,
+    exclude_owners: Vec<usize>,
+    remotes: GlobSet,
+    remote_owners: Vec<usize>,
+}
+
+// Adds each valid pattern to `builder`, recording its project in `owners`
+fn add_patterns(builder: &mut GlobSetBuilder, owners: &mut Vec<usize>, owner: usize, project_name: &str, patterns: impl Iterator<Item = String>, what: &str, debug: bool) {
+    for pattern_str in patterns {
+        match Glob::new(&pattern_str) {
+            Ok(glob) => { builder.add(glob); owners.push(owner); }
+            Err(e) => {
+                if debug { eprintln!("[Sink] Warning: Invalid {} pattern for project '{}' ('{}'): {}", what, project_name, pattern_str, e); }
+            }
+        }
+    }
+}
+
+// Only valid globs were added, so building can't fail on a pattern
+fn build_set(builder: GlobSetBuilder) -> GlobSet {
+    builder.build().unwrap_or_else(|_| GlobSet::empty())
+}
+
+impl ProjectMatcher {
+    pub fn new(config: &Config, debug: bool) -> Self {
+        let mut ordered: Vec<(&String, &ProjectConfig)> = config.projects.iter().collect();
+        ordered.sort_by(|(a_name, a), (b_name, b)| b.priority.cmp(&a.priority).then_with(|| a_name.cmp(b_name)));
+
+        let (mut paths, mut excludes, mut remotes) = (GlobSetBuilder::new(), GlobSetBuilder::new(), GlobSetBuilder::new());
+        let (mut path_owners, mut exclude_owners, mut remote_owners) = (Vec::new(), Vec::new(), Vec::new());
+        let mut projects = Vec::new();
+        for (index, (name, project)) in ordered.into_iter().enumerate() {
+            add_patterns(&mut paths, &mut path_owners, index, name, project.patterns.iter().cloned(), "glob", debug);
+            add_patterns(&mut excludes, &mut exclude_owners, index, name, project.exclude.iter().cloned(), "exclude", debug);
+            add_patterns(&mut remotes, &mut remote_owners, index, name, project.remotes.iter().map(|r| normalise_remote_url(r)), "remote", debug);
+            projects.push(name.clone());
+        }
+        ProjectMatcher {
+            projects,
+            paths: build_set(paths),
+            path_owners,
+            excludes: build_set(excludes),
+            exclude_owners,
+            remotes: build_set(remotes),
+            remote_owners,
+        }
+    }
+
+    // Names of the projects matching a file, in matching order. `remote_urls` (normalised) is only asked for when a
+    // project has remote patterns.
+    pub fn matching_projects(&self, file_path: &Path, remote_urls: impl FnOnce() -> Vec<String>) -> Vec<&str> {
+        let mut matched = vec![false; self.projects.len()];
+        for index in self.paths.matches(file_path) { matched[self.path_owners[index]] = true; }
+        if !self.remote_owners.is_empty() {
+            for url in remote_urls() {
+                for index in self.remotes.matches(&url) { matched[self.remote_owners[index]] = true; }
+            }
+        }
+        for index in self.excludes.matches(file_path) { matched[self.exclude_owners[index]] = false; }
+        self.projects.iter().zip(matched).filter(|(_, is_match)| *is_match).map(|(name, _)| name.as_str()).collect()
+    }
+}
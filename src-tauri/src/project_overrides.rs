@@ -0,0 +1,81 @@
+#![allow(clippy::all)]
+use crate::todo_processing::IGNORE_LINE_DIRECTIVE;
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+// --- Per-file project overrides ---
+// A file can name its own project, which wins over project patterns, packages and repositories: a markdown file in
+// its YAML front matter, any file with a directive comment near its top (see below). Lines holding IGNORE_LINE don't count.
+
+pub const PROJECT_DIRECTIVE: &str = "UNITODO_PROJECT"; // UNITODO_IGNORE_LINE
+// `UNITODO_PROJECT: name` only counts within the first N lines of a file // UNITODO_IGNORE_LINE
+pub const PROJECT_DIRECTIVE_MAX_LINE: u64 = 10;
+// Front matter longer than this is not looked into
+const FRONT_MATTER_MAX_LINES: usize = 100;
+
+fn is_markdown(path: &Path) -> bool {
+    path.extension().map_or(false, |e| matches!(e.to_string_lossy().to_lowercase().as_str(), "md" | "markdown" | "mdx"))
+}
+
+fn unquote(value: &str) -> &str {
+    let value = value.trim();
+    value.strip_prefix('"').and_then(|v| v.strip_suffix('"'))
+        .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+        .unwrap_or(value)
+}
+
+// `project` under `unitodo`, as `unitodo: { project: Ceal }` or as an indented `project: Ceal` below `unitodo:`
+fn front_matter_project(front_matter: &[String]) -> Option<String> {
+    let mut lines = front_matter.iter();
+    while let Some(line) = lines.next() {
+        let Some(rest) = line.strip_prefix("unitodo:") else { continue };
+        let rest = rest.split(" #").next().unwrap_or("").trim();
+        if let Some(inline) = rest.strip_prefix('{').and_then(|r| r.strip_suffix('}')) {
+            return inline.split(',').find_map(|entry| {
+                let (key, value) = entry.split_once(':')?;
+                if key.trim() == "project" { Some(unquote(value).to_string()) } else { None }
+            }).filter(|p| !p.is_empty());
+        }
+        if !rest.is_empty() { return None; }
+        for nested in lines.by_ref() {
+            if nested.trim().is_empty() || nested.trim_start().starts_with('#') { continue; }
+            if !nested.starts_with(char::is_whitespace) { break; } // Back at the top level
+            if let Some((key, value)) = nested.trim().split_once(':') {
+                if key.trim() == "project" {
+                    let project = unquote(value.split(" #").next().unwrap_or("")).to_string();
+                    return if project.is_empty() { None } else { Some(project) };
+                }
+            }
+        }
+        return None;
+    }
+    None
+}
+
+// The name after `UNITODO_PROJECT:`, without a comment's closing delimiter // UNITODO_IGNORE_LINE
+fn directive_project(line: &str) -> Option<String> {
+    let rest = &line[line.find(PROJECT_DIRECTIVE)? + PROJECT_DIRECTIVE.len()..];
+    let rest = rest.trim_start().strip_prefix(':').unwrap_or(rest);
+    let rest = ["-->", "*/", "#}", "--}}"].iter().fold(rest.trim(), |r, closer| r.strip_suffix(closer).unwrap_or(r).trim_end());
+    let project = unquote(rest).to_string();
+    if project.is_empty() { None } else { Some(project) }
+}
+
+// The project a file assigns itself, if any; only the start of the file is read
+pub fn file_project_override(path: &Path) -> Option<String> {
+    let file = File::open(path).ok()?;
+    let mut lines = BufReader::new(file).lines().map_while(Result::ok);
+    let mut head: Vec<String> = lines.by_ref().take(PROJECT_DIRECTIVE_MAX_LINE as usize).collect();
+
+    if is_markdown(path) && head.first().map_or(false, |first| first.trim_end() == "---") {
+        head.extend(lines.take(FRONT_MATTER_MAX_LINES.saturating_sub(head.len())));
+        if let Some(end) = head.iter().skip(1).position(|l| matches!(l.trim_end(), "---" | "...")) {
+            if let Some(project) = front_matter_project(&head[1..end + 1]) { return Some(project); }
+        }
+    }
+    head.iter().take(PROJECT_DIRECTIVE_MAX_LINE as usize)
+        .filter(|line| !line.contains(IGNORE_LINE_DIRECTIVE))
+        .find_map(|line| directive_project(line))
+}
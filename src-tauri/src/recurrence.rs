@@ -0,0 +1,49 @@
+#![allow(clippy::all)]
+use crate::natural_dates::parse_weekday;
+use crate::todo_parsing::{parse_field_date, parse_field_duration, is_field_date_only};
+
+use chrono::{Datelike, Duration, NaiveDateTime, Weekday};
+
+// --- Recurring todos: `@every(1w)`, `@every(3d)`, `@every(mon,thu)` ---
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Recurrence {
+    Interval(u64),         // Seconds, from a duration like `1w` or `12h`
+    Weekdays(Vec<Weekday>),
+}
+
+pub fn parse_recurrence(raw: &str) -> Option<Recurrence> {
+    let raw = raw.trim().to_lowercase();
+    let weekdays: Option<Vec<Weekday>> = raw.split(',').map(|day| parse_weekday(day.trim())).collect();
+    match weekdays {
+        Some(days) if !days.is_empty() => Some(Recurrence::Weekdays(days)),
+        _ => parse_field_duration(&raw).filter(|&seconds| seconds > 0).map(Recurrence::Interval),
+    }
+}
+
+// The `@due` value of the next instance, strictly after `now` (local time). Intervals advance from the current due date
+// (or today) so a weekly chore keeps its weekday; weekday lists pick the next listed day. A due time is kept. None if
+// the next date is out of range.
+pub fn next_due_date(recurrence: &Recurrence, current_due: Option<&str>, now: &NaiveDateTime) -> Option<String> {
+    let current = current_due.and_then(|raw| parse_field_date(raw).map(|date| (date, is_field_date_only(raw))));
+    let (base, date_only) = current.unwrap_or((now.date().and_hms_opt(0, 0, 0)?, true));
+
+    let next = match recurrence {
+        Recurrence::Interval(seconds) if date_only => {
+            let step_days = i64::try_from((*seconds / 86400).max(1)).ok()?;
+            let steps = (now.date() - base.date()).num_days().max(0) / step_days + 1;
+            base.checked_add_signed(Duration::try_days(step_days.checked_mul(steps)?)?)?
+        }
+        Recurrence::Interval(seconds) => {
+            let step_seconds = i64::try_from(*seconds).ok()?;
+            let steps = (*now - base).num_seconds().max(0) / step_seconds + 1;
+            base.checked_add_signed(Duration::try_seconds(step_seconds.checked_mul(steps)?)?)?
+        }
+        Recurrence::Weekdays(days) => {
+            let mut next = now.date().and_time(base.time()).checked_add_signed(Duration::days(1))?;
+            while !days.contains(&next.weekday()) { next = next.checked_add_signed(Duration::days(1))?; }
+            next
+        }
+    };
+    Some(if date_only { next.format("%Y-%m-%d").to_string() } else { next.format("%Y-%m-%d %H:%M").to_string() })
+}
@@ -0,0 +1,375 @@
+#![allow(clippy::all)]
+use crate::config_io::get_data_file_path;
+use crate::config_models::Config;
+use crate::file_operations::append_todo_line;
+use crate::fingerprint::{content_fingerprint, normalise_todo_content, TodoText, OTHER_FILE_SIMILARITY, SAME_FILE_SIMILARITY};
+use crate::history::{append_history, record_history, state_event_kind, FileEvents};
+use crate::todo_models::{DisappearedTodo, HistoryEvent, HistoryEventKind, ProcessedTodosOutput, TodoCategoryData};
+use crate::utils::{find_git_repo_root, get_append_file_path_in_dir};
+
+use chrono::Local;
+use lazy_static::lazy_static;
+use parking_lot::Mutex as ParkingMutex;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// --- Changes between consecutive scans ---
+// Each refresh of the todo list is compared with the previous one for the same search paths, kept next to the config so todos deleted
+// while Unitodo was closed are still noticed. Todos are matched by id, or by fingerprint when they have none.
+// Vanished todos go to a disappeared bucket for review: restoring writes them back, confirming records them as completed.
+
+pub const SCAN_STATE_FILE_NAME: &str = "scan_state.json";
+pub const DISAPPEARED_FILE_NAME: &str = "disappeared.json";
+// Previous todos an edited todo without an id is compared with, so scans of large changes stay quick
+const MAX_COMPARISONS_PER_TODO: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct ScannedTodo {
+    id: Option<String>,
+    #[serde(default)]
+    fingerprint: String,
+    location: String,
+    project: String,
+    status: String,
+    content: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ScanState {
+    scans: HashMap<String, BTreeMap<String, ScannedTodo>>, // Search paths -> match key -> todo
+    #[serde(skip)]
+    recorded_keys: HashSet<String>, // Todos written by Unitodo since the last scan, not to be reported again as appeared
+}
+
+lazy_static! {
+    static ref SCAN_STATE: ParkingMutex<Option<ScanState>> = ParkingMutex::new(None);
+    static ref DISAPPEARED_FILE_MUTEX: ParkingMutex<()> = ParkingMutex::new(());
+}
+
+fn file_of(location: &str) -> &str {
+    location.rsplit_once(':').map_or(location, |(file, _)| file)
+}
+
+// Key a todo is matched by across scans: its id, or its fingerprint when it has none
+pub fn todo_match_key(id: Option<&str>, fingerprint: &str) -> String {
+    id.unwrap_or(fingerprint).to_string()
+}
+
+fn read_json_file<T: Default + for<'de> Deserialize<'de>>(file_name: &str) -> io::Result<T> {
+    let path = get_data_file_path(file_name)?;
+    match fs::read_to_string(&path) {
+        Ok(contents) => serde_json::from_str(&contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Failed to parse {}: {}", path.display(), e))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(T::default()),
+        Err(e) => Err(e),
+    }
+}
+
+fn write_json_file<T: Serialize>(file_name: &str, value: &T) -> io::Result<()> {
+    let path = get_data_file_path(file_name)?;
+    if let Some(parent) = path.parent() { fs::create_dir_all(parent)?; }
+    let json = serde_json::to_string(value).map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Failed to serialize {}: {}", file_name, e)))?;
+    let temp_path = path.with_extension("tmp");
+    let mut temp_file = File::create(&temp_path)?;
+    temp_file.write_all(json.as_bytes())?;
+    temp_file.sync_all()?;
+    fs::rename(&temp_path, &path)
+}
+
+fn with_scan_state<R>(f: impl FnOnce(&mut ScanState) -> R) -> R {
+    let mut guard = SCAN_STATE.lock();
+    let state = guard.get_or_insert_with(|| read_json_file(SCAN_STATE_FILE_NAME).unwrap_or_else(|e| {
+        log::warn!("Starting from an empty scan state: {}", e);
+        ScanState::default()
+    }));
+    f(state)
+}
+
+fn save_scan_state(state: &ScanState) {
+    if let Err(e) = write_json_file(SCAN_STATE_FILE_NAME, state) { log::warn!("Failed to save the scan state: {}", e); }
+}
+
+// Keeps the previous scans in line with changes Unitodo wrote itself, so they aren't reported again by the next scan.
+// A todo without an id keeps its key, and so its fingerprint, through edits made from Unitodo.
+pub fn note_recorded_events(events: &[HistoryEvent]) {
+    with_scan_state(|state| {
+        let mut changed = false;
+        for event in events {
+            let mut known = false;
+            for scan in state.scans.values_mut() {
+                let old_key = scan.iter().find(|(_, t)| match (&event.todo_id, &t.id) {
+                    (Some(id), Some(scanned_id)) => id == scanned_id,
+                    _ => t.location == event.location,
+                }).map(|(k, _)| k.clone());
+                let Some(old_key) = old_key else { continue };
+                let Some(before) = scan.remove(&old_key) else { continue };
+                scan.insert(event.todo_id.clone().unwrap_or(old_key), ScannedTodo {
+                    id: event.todo_id.clone(),
+                    fingerprint: before.fingerprint,
+                    location: event.location.clone(),
+                    project: event.project.clone(),
+                    status: event.status.clone(),
+                    content: event.content.clone(),
+                });
+                known = true;
+                changed = true;
+            }
+            if !known && matches!(event.kind, HistoryEventKind::Created | HistoryEventKind::Restored) {
+                let key = event.todo_id.clone().unwrap_or_else(|| content_fingerprint(file_of(&event.location), &event.content));
+                state.recorded_keys.insert(key);
+            }
+        }
+        if changed { save_scan_state(state); }
+    })
+}
+
+// Pairs the todos of a scan, by (category, todo) index, with the key of the same todo in the previous scan: by id or
+// fingerprint first, then a previous todo without an id by the most similar text, in the same file or, needing a closer
+// match, in another one.
+fn match_previous_todos(previous: &BTreeMap<String, ScannedTodo>, categories: &[TodoCategoryData]) -> HashMap<(usize, usize), String> {
+    let mut matched: HashMap<(usize, usize), String> = HashMap::new();
+    let mut used: HashSet<&str> = HashSet::new();
+    let mut unmatched = Vec::new();
+    let mut seen_locations: HashSet<&str> = HashSet::new();
+    for (category_index, category) in categories.iter().enumerate() {
+        for (todo_index, todo) in category.todos.iter().enumerate() {
+            if !seen_locations.insert(todo.location.as_str()) { continue; } // Another project's copy of a todo
+            match previous.get_key_value(&todo_match_key(todo.id.as_deref(), &todo.fingerprint)) {
+                Some((key, _)) if used.insert(key.as_str()) => { matched.insert((category_index, todo_index), key.clone()); }
+                _ => unmatched.push((category_index, todo_index)),
+            }
+        }
+    }
+
+    let candidates: Vec<(&String, &str, TodoText)> = previous.iter()
+        .filter(|(key, before)| before.id.is_none() && !used.contains(key.as_str()))
+        .map(|(key, before)| (key, file_of(&before.location), TodoText::new(&before.content)))
+        .collect();
+    if candidates.is_empty() { return matched; }
+    let line_of = |location: &str| location.rsplit_once(':').and_then(|(_, line)| line.parse::<usize>().ok()).unwrap_or(0);
+    let mut candidates_by_file: BTreeMap<&str, Vec<(usize, usize)>> = BTreeMap::new(); // File -> (line, candidate index)
+    for (candidate_index, (key, file, _)) in candidates.iter().enumerate() {
+        candidates_by_file.entry(file).or_default().push((line_of(&previous[*key].location), candidate_index));
+    }
+    for in_file in candidates_by_file.values_mut() { in_file.sort(); }
+
+    // Each todo is compared with the previous todos of its file nearest its line first and, without a match there, with
+    // other files, up to MAX_COMPARISONS_PER_TODO texts that could be similar enough
+    let mut pairs: Vec<(f64, (usize, usize), usize)> = Vec::new();
+    let mut capped = 0;
+    for &(category_index, todo_index) in &unmatched {
+        let todo = &categories[category_index].todos[todo_index];
+        let (file, line, text) = (file_of(&todo.location), line_of(&todo.location), TodoText::new(&todo.content));
+        let in_file = candidates_by_file.get(file).map_or(&[][..], Vec::as_slice);
+        let split = in_file.partition_point(|&(candidate_line, _)| candidate_line < line);
+        let (mut below, mut above) = (in_file[..split].iter().rev().peekable(), in_file[split..].iter().peekable());
+        let nearest_first = std::iter::from_fn(|| match (below.peek(), above.peek()) {
+            (Some(b), Some(a)) => if line - b.0 <= a.0 - line { below.next() } else { above.next() },
+            _ => below.next().or_else(|| above.next()),
+        });
+        let same_file = nearest_first.map(|&(_, candidate_index)| (candidate_index, true));
+        let other_files = candidates_by_file.iter().filter(|(f, _)| **f != file).flat_map(|(_, c)| c.iter().map(|&(_, candidate_index)| (candidate_index, false)));
+        let (mut comparisons, pairs_before) = (0, pairs.len());
+        for (candidate_index, in_same_file) in same_file.chain(other_files) {
+            if !in_same_file && pairs.len() > pairs_before { break; }
+            let threshold = if in_same_file { SAME_FILE_SIMILARITY } else { OTHER_FILE_SIMILARITY };
+            let before = &candidates[candidate_index].2;
+            if !before.could_reach(&text, threshold) { continue; }
+            if comparisons == MAX_COMPARISONS_PER_TODO { capped += 1; break; }
+            comparisons += 1;
+            let score = before.similarity(&text);
+            if score >= threshold { pairs.push((score, (category_index, todo_index), candidate_index)); }
+        }
+    }
+    if capped > 0 { log::info!("Compared {} edited todos with only {} previous todos each", capped, MAX_COMPARISONS_PER_TODO); }
+    pairs.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+    let mut taken: HashSet<usize> = HashSet::new();
+    for (_, todo_index, candidate_index) in pairs {
+        if matched.contains_key(&todo_index) || taken.contains(&candidate_index) { continue; }
+        taken.insert(candidate_index);
+        matched.insert(todo_index, candidates[candidate_index].0.clone());
+    }
+    matched
+}
+
+// Gives todos unchanged since the last recorded scan the fingerprint they were recorded with, so every scan agrees with
+// GetTodos on fingerprints kept through earlier edits. Only reads the recorded scan.
+pub fn apply_known_fingerprints(config: &Config, categories: &mut [TodoCategoryData]) {
+    with_scan_state(|state| {
+        let Some(previous) = state.scans.get(&config.rg.paths.join("\n")) else { return };
+        let known: HashMap<(&str, &str), &str> = previous.values()
+            .filter(|before| !before.fingerprint.is_empty())
+            .map(|before| ((before.location.as_str(), before.content.as_str()), before.fingerprint.as_str()))
+            .collect();
+        for todo in categories.iter_mut().flat_map(|c| c.todos.iter_mut()) {
+            if let Some(fingerprint) = known.get(&(todo.location.as_str(), todo.content.as_str())) { todo.fingerprint = fingerprint.to_string(); }
+        }
+    })
+}
+
+// Compares a scan with the previous scan of the same search paths, records what appeared, vanished, was edited, moved to
+// another file or changed state, and keeps the disappeared bucket up to date. Todos matched to the previous scan keep
+// its fingerprint. The first scan of a set of paths only sets the baseline. Todos in paths the scan couldn't read are
+// carried over as they were rather than reported as vanished.
+pub fn record_scan_changes(config: &Config, processed: &mut ProcessedTodosOutput) {
+    let unscanned: Vec<&Path> = processed.unscanned_paths.iter().map(Path::new).collect();
+    let categories = &mut processed.categories;
+    let now = Local::now().timestamp();
+    let event = |kind: HistoryEventKind, todo: &ScannedTodo, previous: Option<String>| HistoryEvent {
+        at: now,
+        kind,
+        todo_id: todo.id.clone(),
+        location: todo.location.clone(),
+        project: todo.project.clone(),
+        status: todo.status.clone(),
+        content: todo.content.clone(),
+        previous,
+    };
+    let mut events = Vec::new();
+    let mut vanished = Vec::new();
+    let mut reappeared = Vec::new();
+    with_scan_state(|state| {
+        let paths_key = config.rg.paths.join("\n");
+        let previous_scan = state.scans.get(&paths_key);
+        let matched = previous_scan.map(|previous| match_previous_todos(previous, categories)).unwrap_or_default();
+
+        let mut current: BTreeMap<String, ScannedTodo> = BTreeMap::new();
+        let mut previous_keys: HashMap<String, String> = HashMap::new(); // Current key -> previous key
+        let mut fingerprints_by_location: HashMap<String, String> = HashMap::new();
+        for (category_index, category) in categories.iter_mut().enumerate() {
+            for (todo_index, todo) in category.todos.iter_mut().enumerate() {
+                // A todo listed under several projects is recorded once, under the first
+                if let Some(fingerprint) = fingerprints_by_location.get(&todo.location) { todo.fingerprint = fingerprint.clone(); continue; }
+                let previous_key = matched.get(&(category_index, todo_index));
+                if let Some(before) = previous_key.and_then(|key| previous_scan?.get(key)) {
+                    if !before.fingerprint.is_empty() { todo.fingerprint = before.fingerprint.clone(); }
+                }
+                fingerprints_by_location.insert(todo.location.clone(), todo.fingerprint.clone());
+                let base_key = todo_match_key(todo.id.as_deref(), &todo.fingerprint);
+                let mut key = base_key.clone();
+                let mut occurrence = 1;
+                while current.contains_key(&key) { occurrence += 1; key = format!("{}\n#{}", base_key, occurrence); }
+                if let Some(previous_key) = previous_key { previous_keys.insert(key.clone(), previous_key.clone()); }
+                current.insert(key, ScannedTodo {
+                    id: todo.id.clone(),
+                    fingerprint: todo.fingerprint.clone(),
+                    location: todo.location.clone(),
+                    project: category.name.clone(),
+                    status: todo.status.clone(),
+                    content: todo.content.clone(),
+                });
+            }
+        }
+
+        for (key, before) in previous_scan.into_iter().flatten() {
+            if !unscanned.iter().any(|path| Path::new(file_of(&before.location)).starts_with(path)) || current.contains_key(key) { continue; }
+            current.insert(key.clone(), before.clone());
+            previous_keys.insert(key.clone(), key.clone());
+        }
+
+        if previous_scan == Some(&current) { return; }
+        let previous = state.scans.insert(paths_key, current.clone());
+        save_scan_state(state);
+        let Some(previous) = previous else { return };
+
+        for (key, todo) in &current {
+            match previous_keys.get(key).and_then(|previous_key| previous.get(previous_key)) {
+                None if state.recorded_keys.remove(key) || state.recorded_keys.remove(&content_fingerprint(file_of(&todo.location), &todo.content)) => {}
+                None => { events.push(event(HistoryEventKind::Appeared, todo, None)); reappeared.push(key.clone()); }
+                Some(before) => {
+                    if normalise_todo_content(&before.content) != normalise_todo_content(&todo.content) { events.push(event(HistoryEventKind::Edited, todo, Some(before.content.clone()))); }
+                    if file_of(&before.location) != file_of(&todo.location) { events.push(event(HistoryEventKind::Moved, todo, Some(before.location.clone()))); }
+                    if before.status != todo.status { events.push(event(state_event_kind(config, &todo.status), todo, Some(before.status.clone()))); }
+                }
+            }
+        }
+        let still_present: HashSet<&String> = previous_keys.values().collect();
+        for (key, todo) in &previous {
+            if still_present.contains(key) { continue; }
+            events.push(event(HistoryEventKind::Disappeared, todo, None));
+            vanished.push(DisappearedTodo {
+                key: key.clone(),
+                todo_id: todo.id.clone(),
+                location: todo.location.clone(),
+                project: todo.project.clone(),
+                status: todo.status.clone(),
+                content: todo.content.clone(),
+                disappeared_at: now,
+            });
+        }
+    });
+
+    if let Err(e) = append_history(&events) { log::warn!("Failed to write {} history event(s): {}", events.len(), e); }
+    if vanished.is_empty() && reappeared.is_empty() { return; }
+    let _guard = DISAPPEARED_FILE_MUTEX.lock();
+    let update = (|| {
+        let mut bucket: Vec<DisappearedTodo> = read_json_file(DISAPPEARED_FILE_NAME)?;
+        bucket.retain(|d| !reappeared.contains(&d.key) && !vanished.iter().any(|v| v.key == d.key));
+        bucket.extend(vanished.into_iter().filter(|v| !config.todo_state_of(&v.status).map_or(false, |s| s.is_closed())));
+        write_json_file(DISAPPEARED_FILE_NAME, &bucket)
+    })();
+    if let Err(e) = update { log::warn!("Failed to update the disappeared todos: {}", e); }
+}
+
+// Todos that vanished from a scan while still open, most recent first
+pub fn list_disappeared_todos() -> io::Result<Vec<DisappearedTodo>> {
+    let _guard = DISAPPEARED_FILE_MUTEX.lock();
+    let mut bucket: Vec<DisappearedTodo> = read_json_file(DISAPPEARED_FILE_NAME)?;
+    bucket.sort_by(|a, b| b.disappeared_at.cmp(&a.disappeared_at).then_with(|| a.location.cmp(&b.location)));
+    Ok(bucket)
+}
+
+fn take_disappeared_todo(key: &str) -> io::Result<DisappearedTodo> {
+    let _guard = DISAPPEARED_FILE_MUTEX.lock();
+    let mut bucket: Vec<DisappearedTodo> = read_json_file(DISAPPEARED_FILE_NAME)?;
+    let index = bucket.iter().position(|d| d.key == key)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Todo not found among disappeared todos"))?;
+    let taken = bucket.remove(index);
+    write_json_file(DISAPPEARED_FILE_NAME, &bucket)?;
+    Ok(taken)
+}
+
+// Writes a disappeared todo back, appended to the file new todos of its project or repository go to (next to its old
+// file otherwise), since the lines around its old location may be gone. Returns its new location.
+pub fn restore_disappeared_todo(config: &Config, key: &str) -> io::Result<String> {
+    let todo = list_disappeared_todos()?.into_iter().find(|d| d.key == key)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Todo not found among disappeared todos"))?;
+    let target_path: PathBuf = match config.projects.get(&todo.project).and_then(|p| p.append_file_path.as_ref()) {
+        Some(append_file_path) => PathBuf::from(append_file_path),
+        None => {
+            let mut dir = Path::new(file_of(&todo.location)).parent().map(Path::to_path_buf).unwrap_or_default();
+            while !dir.is_dir() && dir.pop() {}
+            match find_git_repo_root(&dir) {
+                Ok(Some(repo_root)) => get_append_file_path_in_dir(&repo_root, &config.default_append_basename),
+                _ => get_append_file_path_in_dir(&dir, &config.default_append_basename),
+            }
+        }
+    };
+    let separator = if todo.status.ends_with(char::is_whitespace) { "" } else { " " };
+    let line = format!("{}{}{}", todo.status, separator, todo.content);
+    let file_events = FileEvents::new(config, &target_path.to_string_lossy());
+    let line_number = append_todo_line(&target_path, &line)?;
+    take_disappeared_todo(key)?;
+    let location = format!("{}:{}", target_path.display(), line_number);
+    record_history(file_events.event(HistoryEventKind::Restored, &location, &line, Some(todo.location.clone())).into_iter().collect());
+    Ok(location)
+}
+
+// Records a disappeared todo as completed: it was removed because the work was done
+pub fn confirm_disappeared_todo_done(key: &str) -> io::Result<DisappearedTodo> {
+    let todo = take_disappeared_todo(key)?;
+    record_history(vec![HistoryEvent {
+        at: Local::now().timestamp(),
+        kind: HistoryEventKind::Completed,
+        todo_id: todo.todo_id.clone(),
+        location: todo.location.clone(),
+        project: todo.project.clone(),
+        status: todo.status.clone(),
+        content: todo.content.clone(),
+        previous: Some(todo.status.clone()),
+    }]);
+    Ok(todo)
+}
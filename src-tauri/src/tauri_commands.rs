@@ -19,6 +19,19 @@ use crate::unitodo::{ // Corrected path to unitodo
     DeleteProfileRequest, DeleteProfileResponse,
     ConfigMessage as ProtoConfigMessage, // For update_config_command
     CycleTodoStateRequest, CycleTodoStateResponse, // Added for the new command
+    GetDependencyGraphRequest, GetDependencyGraphResponse,
+    UpdateTodoFieldsRequest, UpdateTodoFieldsResponse,
+    MigrateFormatRequest, MigrateFormatResponse,
+    GetAgendaRequest, GetAgendaResponse,
+    QueryTodosRequest, QueryTodosResponse,
+    GetViewRequest, GetViewResponse,
+    SnoozeRequest, SnoozeResponse,
+    StartTimerRequest, StartTimerResponse,
+    StopTimerRequest, StopTimerResponse,
+    GetTimeReportRequest, GetTimeReportResponse,
+    GetHistoryRequest, GetHistoryResponse,
+    GetDisappearedTodosRequest, GetDisappearedTodosResponse,
+    ResolveDisappearedTodoRequest, ResolveDisappearedTodoResponse,
 };
 use crate::AppState; // Assuming AppState is defined in main.rs or another accessible module
 
@@ -61,12 +74,13 @@ pub async fn update_config_command(
 
 #[tauri::command]
 pub async fn get_todos_command(
+    payload: Option<GetTodosRequest>, // Omitted by callers that want the default flat listing
     app_config_state: tauri::State<'_, Arc<RwLock<AppConfiguration>>>,
 ) -> Result<GetTodosResponse, String> {
     let service = MyTodoService {
         config_state: app_config_state.inner().clone(),
     };
-    match service.get_todos(Request::new(GetTodosRequest {})).await {
+    match service.get_todos(Request::new(payload.unwrap_or_default())).await {
         Ok(response) => Ok(response.into_inner()),
         Err(status) => Err(status.to_string()),
     }
@@ -128,6 +142,186 @@ pub async fn cycle_todo_state_command(
     }
 }
 
+#[tauri::command]
+pub async fn get_dependency_graph_command(
+    app_config_state: tauri::State<'_, Arc<RwLock<AppConfiguration>>>,
+) -> Result<GetDependencyGraphResponse, String> {
+    let service = MyTodoService {
+        config_state: app_config_state.inner().clone(),
+    };
+    match service.get_dependency_graph(Request::new(GetDependencyGraphRequest {})).await {
+        Ok(response) => Ok(response.into_inner()),
+        Err(status) => Err(status.to_string()),
+    }
+}
+
+#[tauri::command]
+pub async fn update_todo_fields_command(
+    payload: UpdateTodoFieldsRequest,
+    app_config_state: tauri::State<'_, Arc<RwLock<AppConfiguration>>>,
+) -> Result<UpdateTodoFieldsResponse, String> {
+    let service = MyTodoService {
+        config_state: app_config_state.inner().clone(),
+    };
+    match service.update_todo_fields(Request::new(payload)).await {
+        Ok(response) => Ok(response.into_inner()),
+        Err(status) => Err(status.to_string()),
+    }
+}
+
+#[tauri::command]
+pub async fn snooze_command(
+    payload: SnoozeRequest,
+    app_config_state: tauri::State<'_, Arc<RwLock<AppConfiguration>>>,
+) -> Result<SnoozeResponse, String> {
+    let service = MyTodoService {
+        config_state: app_config_state.inner().clone(),
+    };
+    match service.snooze(Request::new(payload)).await {
+        Ok(response) => Ok(response.into_inner()),
+        Err(status) => Err(status.to_string()),
+    }
+}
+
+#[tauri::command]
+pub async fn start_timer_command(
+    payload: StartTimerRequest,
+    app_config_state: tauri::State<'_, Arc<RwLock<AppConfiguration>>>,
+) -> Result<StartTimerResponse, String> {
+    let service = MyTodoService {
+        config_state: app_config_state.inner().clone(),
+    };
+    match service.start_timer(Request::new(payload)).await {
+        Ok(response) => Ok(response.into_inner()),
+        Err(status) => Err(status.to_string()),
+    }
+}
+
+#[tauri::command]
+pub async fn stop_timer_command(
+    payload: StopTimerRequest,
+    app_config_state: tauri::State<'_, Arc<RwLock<AppConfiguration>>>,
+) -> Result<StopTimerResponse, String> {
+    let service = MyTodoService {
+        config_state: app_config_state.inner().clone(),
+    };
+    match service.stop_timer(Request::new(payload)).await {
+        Ok(response) => Ok(response.into_inner()),
+        Err(status) => Err(status.to_string()),
+    }
+}
+
+#[tauri::command]
+pub async fn get_time_report_command(
+    payload: Option<GetTimeReportRequest>,
+    app_config_state: tauri::State<'_, Arc<RwLock<AppConfiguration>>>,
+) -> Result<GetTimeReportResponse, String> {
+    let service = MyTodoService {
+        config_state: app_config_state.inner().clone(),
+    };
+    match service.get_time_report(Request::new(payload.unwrap_or_default())).await {
+        Ok(response) => Ok(response.into_inner()),
+        Err(status) => Err(status.to_string()),
+    }
+}
+
+#[tauri::command]
+pub async fn get_history_command(
+    payload: Option<GetHistoryRequest>,
+    app_config_state: tauri::State<'_, Arc<RwLock<AppConfiguration>>>,
+) -> Result<GetHistoryResponse, String> {
+    let service = MyTodoService {
+        config_state: app_config_state.inner().clone(),
+    };
+    match service.get_history(Request::new(payload.unwrap_or_default())).await {
+        Ok(response) => Ok(response.into_inner()),
+        Err(status) => Err(status.to_string()),
+    }
+}
+
+#[tauri::command]
+pub async fn get_disappeared_todos_command(
+    app_config_state: tauri::State<'_, Arc<RwLock<AppConfiguration>>>,
+) -> Result<GetDisappearedTodosResponse, String> {
+    let service = MyTodoService {
+        config_state: app_config_state.inner().clone(),
+    };
+    match service.get_disappeared_todos(Request::new(GetDisappearedTodosRequest {})).await {
+        Ok(response) => Ok(response.into_inner()),
+        Err(status) => Err(status.to_string()),
+    }
+}
+
+#[tauri::command]
+pub async fn resolve_disappeared_todo_command(
+    payload: ResolveDisappearedTodoRequest,
+    app_config_state: tauri::State<'_, Arc<RwLock<AppConfiguration>>>,
+) -> Result<ResolveDisappearedTodoResponse, String> {
+    let service = MyTodoService {
+        config_state: app_config_state.inner().clone(),
+    };
+    match service.resolve_disappeared_todo(Request::new(payload)).await {
+        Ok(response) => Ok(response.into_inner()),
+        Err(status) => Err(status.to_string()),
+    }
+}
+
+#[tauri::command]
+pub async fn migrate_format_command(
+    payload: MigrateFormatRequest,
+    app_config_state: tauri::State<'_, Arc<RwLock<AppConfiguration>>>,
+) -> Result<MigrateFormatResponse, String> {
+    let service = MyTodoService {
+        config_state: app_config_state.inner().clone(),
+    };
+    match service.migrate_format(Request::new(payload)).await {
+        Ok(response) => Ok(response.into_inner()),
+        Err(status) => Err(status.to_string()),
+    }
+}
+
+#[tauri::command]
+pub async fn get_agenda_command(
+    payload: Option<GetAgendaRequest>,
+    app_config_state: tauri::State<'_, Arc<RwLock<AppConfiguration>>>,
+) -> Result<GetAgendaResponse, String> {
+    let service = MyTodoService {
+        config_state: app_config_state.inner().clone(),
+    };
+    match service.get_agenda(Request::new(payload.unwrap_or_default())).await {
+        Ok(response) => Ok(response.into_inner()),
+        Err(status) => Err(status.to_string()),
+    }
+}
+
+#[tauri::command]
+pub async fn query_todos_command(
+    payload: QueryTodosRequest,
+    app_config_state: tauri::State<'_, Arc<RwLock<AppConfiguration>>>,
+) -> Result<QueryTodosResponse, String> {
+    let service = MyTodoService {
+        config_state: app_config_state.inner().clone(),
+    };
+    match service.query_todos(Request::new(payload)).await {
+        Ok(response) => Ok(response.into_inner()),
+        Err(status) => Err(status.to_string()),
+    }
+}
+
+#[tauri::command]
+pub async fn get_view_command(
+    payload: GetViewRequest,
+    app_config_state: tauri::State<'_, Arc<RwLock<AppConfiguration>>>,
+) -> Result<GetViewResponse, String> {
+    let service = MyTodoService {
+        config_state: app_config_state.inner().clone(),
+    };
+    match service.get_view(Request::new(payload)).await {
+        Ok(response) => Ok(response.into_inner()),
+        Err(status) => Err(status.to_string()),
+    }
+}
+
 #[tauri::command]
 pub async fn get_grpc_port_command(app_state: tauri::State<'_, AppState>) -> Result<Option<u16>, String> {
     let port_option_guard = app_state.grpc_port.read().await;
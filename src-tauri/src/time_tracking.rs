@@ -0,0 +1,188 @@
+#![allow(clippy::all)]
+use crate::config_io::get_data_file_path;
+use crate::todo_models::{ProcessedTodosOutput, TimeReport, TimeTotal, TodoItem};
+use crate::todo_parsing::parse_field_duration;
+
+use chrono::{Duration, Local, NaiveDate, TimeZone};
+use lazy_static::lazy_static;
+use parking_lot::Mutex as ParkingMutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, Write};
+
+// --- Time tracking: start/stop timers keyed by todo ID, kept in a JSON-lines log next to the config ---
+
+pub const TIME_LOG_FILE_NAME: &str = "timelog.jsonl";
+
+// One timed interval; `content` and `category` are what the todo looked like when the timer started
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeInterval {
+    pub todo_id: String,
+    pub start: i64,       // Unix seconds
+    pub end: Option<i64>, // None while the timer is running
+    pub content: String,
+    pub category: String,
+}
+
+impl TimeInterval {
+    // Seconds inside `[since, until)`, counting a running interval up to `now`
+    fn seconds_within(&self, since: i64, until: i64, now: i64) -> u64 {
+        let end = self.end.unwrap_or(now).min(until);
+        (end - self.start.max(since)).max(0) as u64
+    }
+}
+
+// Time spent on one todo across all of its intervals
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimeSpent {
+    pub seconds: u64,
+    pub running_since: Option<i64>,
+}
+
+lazy_static! {
+    static ref TIME_LOG_MUTEX: ParkingMutex<()> = ParkingMutex::new(());
+}
+
+pub fn load_time_log() -> io::Result<Vec<TimeInterval>> {
+    let _guard = TIME_LOG_MUTEX.lock();
+    read_time_log()
+}
+
+fn read_time_log() -> io::Result<Vec<TimeInterval>> {
+    let path = get_data_file_path(TIME_LOG_FILE_NAME)?;
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    Ok(contents.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()).filter_map(|(i, line)| {
+        serde_json::from_str(line).map_err(|e| log::warn!("Skipping unreadable time log line {}: {}", i + 1, e)).ok()
+    }).collect())
+}
+
+fn write_time_log(intervals: &[TimeInterval]) -> io::Result<()> {
+    let path = get_data_file_path(TIME_LOG_FILE_NAME)?;
+    if let Some(parent) = path.parent() { fs::create_dir_all(parent)?; }
+    let mut contents = String::new();
+    for interval in intervals {
+        let line = serde_json::to_string(interval).map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Failed to serialize time log entry: {}", e)))?;
+        contents.push_str(&line);
+        contents.push('\n');
+    }
+    let temp_path = path.with_extension("tmp");
+    let mut temp_file = File::create(&temp_path)?;
+    temp_file.write_all(contents.as_bytes())?;
+    temp_file.sync_all()?;
+    fs::rename(&temp_path, &path)
+}
+
+// Opens an interval for `todo_id` at `now`; a todo can only have one running timer
+pub fn start_timer(todo_id: &str, content: &str, category: &str, now: i64) -> io::Result<TimeInterval> {
+    let _guard = TIME_LOG_MUTEX.lock();
+    let mut intervals = read_time_log()?;
+    if intervals.iter().any(|i| i.todo_id == todo_id && i.end.is_none()) {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("A timer is already running for {}", todo_id)));
+    }
+    let interval = TimeInterval { todo_id: todo_id.to_string(), start: now, end: None, content: content.to_string(), category: category.to_string() };
+    intervals.push(interval.clone());
+    write_time_log(&intervals)?;
+    Ok(interval)
+}
+
+// Closes the running interval for `todo_id` at `now` and returns it
+pub fn stop_timer(todo_id: &str, now: i64) -> io::Result<TimeInterval> {
+    let _guard = TIME_LOG_MUTEX.lock();
+    let mut intervals = read_time_log()?;
+    let running = intervals.iter_mut().find(|i| i.todo_id == todo_id && i.end.is_none())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("No timer is running for {}", todo_id)))?;
+    running.end = Some(now.max(running.start));
+    let stopped = running.clone();
+    write_time_log(&intervals)?;
+    Ok(stopped)
+}
+
+pub fn time_spent_by_todo(intervals: &[TimeInterval], now: i64) -> HashMap<String, TimeSpent> {
+    let mut spent: HashMap<String, TimeSpent> = HashMap::new();
+    for interval in intervals {
+        let entry = spent.entry(interval.todo_id.clone()).or_default();
+        entry.seconds += interval.seconds_within(i64::MIN, i64::MAX, now);
+        if interval.end.is_none() { entry.running_since = Some(interval.start); }
+    }
+    spent
+}
+
+// Fills in logged time and the `@est(...)` duration on each todo
+pub fn apply_time_tracking(todos: &mut [TodoItem], spent: &HashMap<String, TimeSpent>) {
+    for todo in todos.iter_mut() {
+        todo.estimate = todo.fields.get("est").and_then(|f| parse_field_duration(&f.raw));
+        if let Some(todo_spent) = todo.id.as_ref().and_then(|id| spent.get(id)) {
+            todo.time_spent = todo_spent.seconds;
+            todo.timer_started = todo_spent.running_since;
+        }
+    }
+}
+
+// The todo with `todo_id` in a scan, with the name of its category
+pub fn find_todo_by_id<'a>(processed: &'a ProcessedTodosOutput, todo_id: &str) -> Option<(&'a TodoItem, &'a str)> {
+    processed.categories.iter()
+        .find_map(|c| c.todos.iter().find(|t| t.id.as_deref() == Some(todo_id)).map(|t| (t, c.name.as_str())))
+}
+
+// Totals logged time inside `[since, until)` per todo, per project (category) and per local day. Todos still found by
+// `processed` are labelled with their current content and category; others keep what was logged when their timer started.
+pub fn build_time_report(intervals: &[TimeInterval], processed: &ProcessedTodosOutput, since: Option<i64>, until: Option<i64>, now: i64) -> TimeReport {
+    let (since, until) = (since.unwrap_or(i64::MIN), until.unwrap_or(i64::MAX));
+    let mut by_todo: BTreeMap<String, TimeTotal> = BTreeMap::new();
+    let mut by_project: BTreeMap<String, TimeTotal> = BTreeMap::new();
+    let mut by_day: BTreeMap<NaiveDate, u64> = BTreeMap::new();
+    let mut report = TimeReport::default();
+
+    for interval in intervals {
+        let seconds = interval.seconds_within(since, until, now);
+        if seconds == 0 { continue; }
+        let current = find_todo_by_id(processed, &interval.todo_id);
+        let (label, category) = current.map_or((interval.content.as_str(), interval.category.as_str()), |(t, c)| (t.content.as_str(), c));
+
+        let todo_total = by_todo.entry(interval.todo_id.clone()).or_insert_with(|| TimeTotal {
+            key: interval.todo_id.clone(),
+            label: label.to_string(),
+            estimate: current.and_then(|(t, _)| t.estimate),
+            ..Default::default()
+        });
+        todo_total.seconds += seconds;
+        let project_total = by_project.entry(category.to_string()).or_insert_with(|| TimeTotal { key: category.to_string(), label: category.to_string(), ..Default::default() });
+        project_total.seconds += seconds;
+        report.total_seconds += seconds;
+
+        for (day, day_seconds) in split_by_local_day(interval.start.max(since), interval.end.unwrap_or(now).min(until)) {
+            *by_day.entry(day).or_default() += day_seconds;
+        }
+    }
+
+    report.by_todo = by_todo.into_values().collect();
+    report.by_todo.sort_by(|a, b| b.seconds.cmp(&a.seconds).then_with(|| a.key.cmp(&b.key)));
+    report.by_project = by_project.into_values().collect();
+    report.by_project.sort_by(|a, b| b.seconds.cmp(&a.seconds).then_with(|| a.key.cmp(&b.key)));
+    report.by_day = by_day.into_iter().map(|(day, seconds)| {
+        let date = day.format("%Y-%m-%d").to_string();
+        TimeTotal { key: date.clone(), label: date, seconds, estimate: None }
+    }).collect();
+    report
+}
+
+// Splits `[start, end)` (unix seconds) at local midnights
+fn split_by_local_day(start: i64, end: i64) -> Vec<(NaiveDate, u64)> {
+    let mut days = Vec::new();
+    let mut cursor = start;
+    while cursor < end {
+        let Some(local) = Local.timestamp_opt(cursor, 0).earliest() else { break };
+        let next_midnight = (local.date_naive() + Duration::days(1)).and_hms_opt(0, 0, 0)
+            .and_then(|midnight| Local.from_local_datetime(&midnight).earliest())
+            .map_or(end, |midnight| midnight.timestamp());
+        let chunk_end = next_midnight.min(end).max(cursor + 1);
+        days.push((local.date_naive(), (chunk_end - cursor) as u64));
+        cursor = chunk_end;
+    }
+    days
+}
@@ -1,5 +1,7 @@
 #![allow(clippy::all)]
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
 
 // --- todo Data Structures for internal logic ---
 #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
@@ -7,13 +9,112 @@ pub struct TodoItem {
     pub content: String,
     pub location: String,
     pub status: String,
+    pub details: Option<TodoDetails>,
+    pub parent: Option<String>, // Location of the enclosing checklist item in the same file
+    pub depth: u32,
+    pub subtasks: Option<SubtaskRollup>, // Set on todos that have nested checklist items
+    pub id: Option<String>, // `@ts5`, `#nanoid` or `##num` from the first word, including its sigil
+    pub fields: BTreeMap<String, TodoField>, // `@name(value)` tokens in the content, keyed by name
+    pub due: Option<i64>,       // `@due(...)` as a unix timestamp (local time)
+    pub scheduled: Option<i64>, // `@scheduled(...)` as a unix timestamp (local time)
+    pub overdue: bool,          // Open and past its due date
+    pub deferred: bool,         // Open with a `@defer(...)` date still ahead
+    pub time_spent: u64,             // Seconds logged by timers, including a running one
+    pub estimate: Option<u64>,       // `@est(...)` in seconds
+    pub timer_started: Option<i64>,  // Unix timestamp the running timer started at
+    pub fingerprint: String,         // Stable key from file, normalised text and neighbouring lines (see fingerprint)
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+pub enum TodoFieldKind {
+    Text,
+    Date,     // `2025-06-01`, `2025-06-01 14:30`, `2025-06-01T14:30:00`, in local time
+    Duration, // `1w2d3h4m5s`, any subset of the units in that order
+    Number,
+}
+
+// A `@name(value)` field; the typed value is read back from `raw` with the todo_parsing helpers for `kind`
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+pub struct TodoField {
+    pub raw: String,
+    pub kind: TodoFieldKind,
+}
+
+// Completion of a todo's direct children; cancelled children are left out of `total`
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+pub struct SubtaskRollup {
+    pub total: u32,
+    pub done: u32,
+}
+
+// Meaning of a marker by its position in a `todo_states` set
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum TodoState {
+    Open,
+    Active,
+    Done,
+    Cancelled,
+}
+
+impl TodoState {
+    pub fn from_index(index: usize) -> Option<TodoState> {
+        match index {
+            0 => Some(TodoState::Open),
+            1 => Some(TodoState::Active),
+            2 => Some(TodoState::Done),
+            3 => Some(TodoState::Cancelled),
+            _ => None,
+        }
+    }
+
+    pub fn index(self) -> usize {
+        match self {
+            TodoState::Open => 0,
+            TodoState::Active => 1,
+            TodoState::Done => 2,
+            TodoState::Cancelled => 3,
+        }
+    }
+
+    pub fn is_closed(self) -> bool {
+        matches!(self, TodoState::Done | TodoState::Cancelled)
+    }
+}
+
+// Continuation lines attached to a todo (1-based, inclusive line range in the todo's file)
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+pub struct TodoDetails {
+    pub text: String,
+    pub start_line: u64,
+    pub end_line: u64,
+}
+
+// A todo with its nested checklist items, for tree-shaped responses
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TodoTreeNode {
+    pub item: TodoItem,
+    pub children: Vec<TodoTreeNode>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct TodoCategoryData {
-    pub name: String,
+    pub key: String, // Stable and unique: `git:<canonical repo root>`, `remote:<normalised origin URL>`, `package:<canonical dir>`, `project:<name>` or `other`
+    pub name: String, // Display name, disambiguated when two repositories share a directory name
+    pub parent_key: Option<String>, // A discovered package's repository category
+    pub parent_name: Option<String>,
     pub icon: String,
     pub todos: Vec<TodoItem>,
+    pub deferred_count: u32, // Deferred todos in the category, whether or not they were left in `todos`
+    pub state_counts: StateCounts, // Deferred todos included
+}
+
+// Todos by state; markers without a state in `todo_states` aren't counted
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct StateCounts {
+    pub open: u32,
+    pub active: u32,
+    pub done: u32,
+    pub cancelled: u32,
 }
 
 impl Ord for TodoCategoryData {
@@ -28,24 +129,204 @@ impl PartialOrd for TodoCategoryData {
     }
 }
 
+// A problem found while scanning that doesn't stop the scan (e.g. unbalanced ignore blocks)
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ScanDiagnostic {
+    pub location: String,
+    pub message: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ProcessedTodosOutput {
     pub categories: Vec<TodoCategoryData>,
+    pub diagnostics: Vec<ScanDiagnostic>,
+    #[serde(default)]
+    pub unscanned_paths: Vec<String>, // Search roots and files that couldn't be read; their todos are unknown, not gone
+}
+
+// A todo in an agenda, with the name of the category it was scanned into
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct AgendaEntry {
+    pub item: TodoItem,
+    pub category: String,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct AgendaDay {
+    pub date: String, // YYYY-MM-DD, local time
+    pub entries: Vec<AgendaEntry>,
+}
+
+// Open todos by day: past due, due or scheduled today (or scheduled earlier), the following days of the range, and without dates
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Agenda {
+    pub overdue: Vec<AgendaEntry>,
+    pub today: Vec<AgendaEntry>,
+    pub upcoming: Vec<AgendaDay>,
+    pub undated: Vec<AgendaEntry>,
+}
+
+// Span of a todo's content, in UTF-16 code units so clients can slice their strings with it
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TextRange {
+    pub start: u32,
+    pub end: u32, // Exclusive
+}
+
+// A todo matched by a query, with the name of its category and the spans of its content matched by text terms
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct QueryMatch {
+    pub item: TodoItem,
+    pub category: String,
+    pub highlights: Vec<TextRange>,
+}
+
+// Matches of a saved view sharing a category, or all of them when the view isn't grouped
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ViewGroup {
+    pub key: String,
+    pub name: String,
+    pub icon: String,
+    pub matches: Vec<QueryMatch>,
+}
+
+// Logged time for a todo, project or day; `estimate` is only set on todos with `@est(...)`
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TimeTotal {
+    pub key: String, // Todo id, category name, or YYYY-MM-DD
+    pub label: String,
+    pub seconds: u64,
+    pub estimate: Option<u64>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TimeReport {
+    pub by_todo: Vec<TimeTotal>,    // Most time first
+    pub by_project: Vec<TimeTotal>, // Most time first
+    pub by_day: Vec<TimeTotal>,     // Chronological, local days
+    pub total_seconds: u64,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryEventKind {
+    Created,       // Added from Unitodo, or spawned by a recurring todo
+    Edited,
+    FieldsChanged,
+    Reopened,      // Moved back to the open state
+    Started,       // Moved to the active state
+    Completed,
+    Cancelled,
+    Appeared,      // Found by a scan without having been created from Unitodo
+    Disappeared,   // No longer found by a scan
+    Moved,         // Found in another file than by the previous scan
+    Restored,      // Written back from the disappeared todos
+}
+
+// One line of the history log. `status` and `content` are the todo's after the event; for scan events without a
+// change of content they're what the scan saw.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEvent {
+    pub at: i64, // Unix seconds
+    pub kind: HistoryEventKind,
+    pub todo_id: Option<String>,
+    pub location: String,
+    pub project: String, // Category name
+    pub status: String,
+    pub content: String,
+    pub previous: Option<String>, // Content before an edit, marker before a state change, or location before a move
+}
+
+// An open todo that vanished between two scans, kept for review
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DisappearedTodo {
+    pub key: String, // The todo's id, or its file and normalised content
+    pub todo_id: Option<String>,
+    pub location: String, // Where it was last seen
+    pub project: String,
+    pub status: String,
+    pub content: String,
+    pub disappeared_at: i64, // Unix seconds
+}
+
+// A todo taking part in `after:`/`blocks:` references. `key` is the todo's id, or its location if it has none
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DependencyNode {
+    pub key: String,
+    pub item: TodoItem,
+    pub category: String,
+    pub open: bool,             // Not done or cancelled
+    pub blocked_by: Vec<String>, // Keys of open prerequisites
+}
+
+// `from` has to be closed before `to` is unblocked
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DependencyEdge {
+    pub from: String,
+    pub to: String,
+}
+
+// A reference to an id that no scanned todo carries
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DanglingReference {
+    pub from: String,
+    pub reference: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DependencyGraph {
+    pub nodes: Vec<DependencyNode>,
+    pub edges: Vec<DependencyEdge>,
+    pub cycles: Vec<Vec<String>>,
+    pub dangling: Vec<DanglingReference>,
+    pub unblocked: Vec<String>, // Keys of open todos without open prerequisites
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum TodoCategoryEnum {
     Project(String),
-    GitRepo(String),
+    GitRepo(String), // Canonical path of the repository root
+    Remote(String), // Normalised `origin` URL, when clones are grouped by remote
+    Package(String), // Canonical directory of a package discovered from its manifest
     Other,
 }
 
 impl TodoCategoryEnum {
+    // Display name (before disambiguation) and icon
     pub fn get_details(&self) -> (String, String) {
         match self {
             TodoCategoryEnum::Project(name) => (name.clone(), "".to_string()), 
-            TodoCategoryEnum::GitRepo(name) => (name.clone(), "󰊢".to_string()), 
+            TodoCategoryEnum::GitRepo(root) => {
+                let name = Path::new(root).file_name().map_or_else(|| root.clone(), |n| n.to_string_lossy().into_owned());
+                (name, "󰊢".to_string())
+            }
+            TodoCategoryEnum::Remote(url) => (url.rsplit('/').next().unwrap_or(url).to_string(), "󰊢".to_string()),
+            TodoCategoryEnum::Package(dir) => {
+                let name = Path::new(dir).file_name().map_or_else(|| dir.clone(), |n| n.to_string_lossy().into_owned());
+                (name, "\u{f487}".to_string())
+            }
             TodoCategoryEnum::Other => ("Other".to_string(), "".to_string()), 
         }
     }
+
+    pub fn key(&self) -> String {
+        match self {
+            TodoCategoryEnum::Project(name) => format!("project:{}", name),
+            TodoCategoryEnum::GitRepo(root) => format!("git:{}", root),
+            TodoCategoryEnum::Remote(url) => format!("remote:{}", url),
+            TodoCategoryEnum::Package(dir) => format!("package:{}", dir),
+            TodoCategoryEnum::Other => "other".to_string(),
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        if key == "other" { return Some(TodoCategoryEnum::Other); }
+        match key.split_once(':') {
+            Some(("project", name)) if !name.is_empty() => Some(TodoCategoryEnum::Project(name.to_string())),
+            Some(("git", root)) if !root.is_empty() => Some(TodoCategoryEnum::GitRepo(root.to_string())),
+            Some(("remote", url)) if !url.is_empty() => Some(TodoCategoryEnum::Remote(url.to_string())),
+            Some(("package", dir)) if !dir.is_empty() => Some(TodoCategoryEnum::Package(dir.to_string())),
+            _ => None,
+        }
+    }
 } 
\ No newline at end of file
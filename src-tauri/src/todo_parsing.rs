@@ -0,0 +1,218 @@
+#![allow(clippy::all)]
+use crate::todo_models::{TodoField, TodoFieldKind};
+
+use chrono::{Local, NaiveDate, NaiveDateTime, TimeZone};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::BTreeMap;
+
+// --- Parsing of the metadata embedded in todo content ---
+
+lazy_static! {
+    // First word of the content: `<priority><id><@@done>`, e.g. `1@AqTDc`, `0#Jl_obVmSA7XCwzp7hkT2r`, `2##12@@ArSOf`
+    static ref FIRST_WORD_RE: Regex = Regex::new(
+        r"^(?P<priority>[A-Za-z0-9][A-Za-z0-9-]*)?(?P<id>@[A-Za-z0-9_-]{5}|##[0-9]+|#[A-Za-z0-9_-]+)?(?P<done>@@[A-Za-z0-9_-]{5})?$"
+    ).unwrap();
+    // `after:<ref>[,<ref>...]` (this todo waits for the referenced ones) and `blocks:<ref>[,<ref>...]` (they wait for this one)
+    static ref REFERENCE_RE: Regex = Regex::new(
+        r"(?:^|\s)(?P<kind>after|blocks):(?P<refs>(?:##[0-9]+|#[A-Za-z0-9_-]+|@[A-Za-z0-9_-]{5})(?:,(?:##[0-9]+|#[A-Za-z0-9_-]+|@[A-Za-z0-9_-]{5}))*)"
+    ).unwrap();
+    // `@name(value)` anywhere after the first word; ids like `@AqTDc` never carry parentheses
+    static ref FIELD_RE: Regex = Regex::new(r"(?:^|\s)@(?P<name>[A-Za-z][A-Za-z0-9_-]*)\((?P<value>[^()\n]*)\)").unwrap();
+    // `#tag` after the first word; ids only appear in the first word, and references are joined to `after:`/`blocks:`
+    static ref TAG_RE: Regex = Regex::new(r"\s#(?P<tag>[A-Za-z][A-Za-z0-9_/-]*)").unwrap();
+    static ref FIELD_NAME_RE: Regex = Regex::new(r"^[A-Za-z][A-Za-z0-9_-]*$").unwrap();
+    static ref DURATION_RE: Regex = Regex::new(r"^(?:(?P<w>\d+)w)?(?:(?P<d>\d+)d)?(?:(?P<h>\d+)h)?(?:(?P<m>\d+)m)?(?:(?P<s>\d+)s)?$").unwrap();
+}
+
+const FIELD_DATE_FORMATS: &[&str] = &["%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S"];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TodoIdentity {
+    pub priority: Option<String>,
+    pub id: Option<String>,         // Including its sigil: `@AqTDc`, `#Jl_obVmSA7XCwzp7hkT2r` or `##12`
+    pub done_stamp: Option<String>, // Without the `@@`
+}
+
+// Parses the first word of a todo's content (the part after the state marker)
+pub fn parse_todo_identity(content: &str) -> TodoIdentity {
+    let first_word = content.split_whitespace().next().unwrap_or("");
+    match FIRST_WORD_RE.captures(first_word) {
+        Some(caps) => TodoIdentity {
+            priority: caps.name("priority").map(|m| m.as_str().to_string()),
+            id: caps.name("id").map(|m| m.as_str().to_string()),
+            done_stamp: caps.name("done").map(|m| m.as_str()[2..].to_string()),
+        },
+        None => TodoIdentity::default(),
+    }
+}
+
+// A first word that is only a priority counts as one when it looks like one (`0`, `01`, `0-1`); otherwise it's ordinary text
+pub fn is_priority_like(priority: &str) -> bool {
+    priority.chars().all(|c| c.is_ascii_digit() || c == '-') && priority.starts_with(|c: char| c.is_ascii_digit())
+}
+
+// `#tag` words in the content, without the `#`, in order and without repeats
+pub fn parse_todo_tags(content: &str) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for caps in TAG_RE.captures_iter(content) {
+        let tag = caps["tag"].to_string();
+        if !tags.contains(&tag) { tags.push(tag); }
+    }
+    tags
+}
+
+// Content with every `@name(value)` field taken out
+pub fn strip_todo_fields(content: &str) -> String {
+    FIELD_RE.replace_all(content, " ").into_owned()
+}
+
+// Puts `id` in the first word of `content` in place of any existing id, keeping the priority and done stamp.
+// A first word that isn't a priority/id word gets the id in front of it as a word of its own.
+pub fn with_todo_id(content: &str, id: &str) -> String {
+    let identity = parse_todo_identity(content);
+    let has_identity_word = identity.id.is_some() || identity.done_stamp.is_some() || identity.priority.as_deref().map_or(false, is_priority_like);
+    if !has_identity_word {
+        return if content.is_empty() { id.to_string() } else { format!("{} {}", id, content) };
+    }
+    let rest = content.split_once(char::is_whitespace).map_or("", |(_, r)| r.trim_start());
+    let done = identity.done_stamp.map_or_else(String::new, |d| format!("@@{}", d));
+    let first_word = format!("{}{}{}", identity.priority.unwrap_or_default(), id, done);
+    if rest.is_empty() { first_word } else { format!("{} {}", first_word, rest) }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReferenceKind {
+    After,
+    Blocks,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TodoReference {
+    pub kind: ReferenceKind,
+    pub target: String, // Referenced id, including its sigil
+}
+
+// Extracts `after:`/`blocks:` references from anywhere in a todo's content
+pub fn parse_todo_references(content: &str) -> Vec<TodoReference> {
+    let mut references = Vec::new();
+    for caps in REFERENCE_RE.captures_iter(content) {
+        let kind = if &caps["kind"] == "after" { ReferenceKind::After } else { ReferenceKind::Blocks };
+        for target in caps["refs"].split(',') {
+            references.push(TodoReference { kind, target: target.to_string() });
+        }
+    }
+    references
+}
+
+// --- `@name(value)` fields ---
+
+// Extracts every `@name(value)` field from a todo's content; when a name repeats, the first occurrence wins
+pub fn parse_todo_fields(content: &str) -> BTreeMap<String, TodoField> {
+    let mut fields = BTreeMap::new();
+    for caps in FIELD_RE.captures_iter(content) {
+        let raw = caps["value"].trim().to_string();
+        fields.entry(caps["name"].to_string()).or_insert_with(|| TodoField { kind: classify_field_value(&raw), raw });
+    }
+    fields
+}
+
+pub fn classify_field_value(raw: &str) -> TodoFieldKind {
+    if parse_field_date(raw).is_some() { TodoFieldKind::Date }
+    else if parse_field_duration(raw).is_some() { TodoFieldKind::Duration }
+    else if parse_field_number(raw).is_some() { TodoFieldKind::Number }
+    else { TodoFieldKind::Text }
+}
+
+// Dates without a time mean the start of that day
+pub fn parse_field_date(raw: &str) -> Option<NaiveDateTime> {
+    if let Ok(date) = NaiveDate::parse_from_str(raw, "%Y-%m-%d") { return date.and_hms_opt(0, 0, 0); }
+    FIELD_DATE_FORMATS.iter().find_map(|format| NaiveDateTime::parse_from_str(raw, format).ok())
+}
+
+// Whether a field date names a whole day rather than a moment in it
+pub fn is_field_date_only(raw: &str) -> bool {
+    NaiveDate::parse_from_str(raw, "%Y-%m-%d").is_ok()
+}
+
+// Whether a due date has passed at `now` (local time). A date-only due lasts the whole day, so it's overdue from the next day on.
+pub fn is_due_date_passed(raw: &str, now: &NaiveDateTime) -> bool {
+    match parse_field_date(raw) {
+        Some(due) if is_field_date_only(raw) => due.date() < now.date(),
+        Some(due) => due < *now,
+        None => false,
+    }
+}
+
+// Whether a field date is still ahead at `now` (local time). A date-only value starts at the beginning of that day.
+pub fn is_field_date_ahead(raw: &str, now: &NaiveDateTime) -> bool {
+    match parse_field_date(raw) {
+        Some(date) if is_field_date_only(raw) => date.date() > now.date(),
+        Some(date) => date > *now,
+        None => false,
+    }
+}
+
+// Unix timestamp of a field date read in the local timezone
+pub fn field_date_to_timestamp(date: &NaiveDateTime) -> Option<i64> {
+    Local.from_local_datetime(date).earliest().map(|dt| dt.timestamp())
+}
+
+// Field date for a unix timestamp, in local time and to the second (the inverse of parse_field_date + field_date_to_timestamp)
+pub fn format_field_timestamp(timestamp: i64) -> Option<String> {
+    Local.timestamp_opt(timestamp, 0).single().map(|dt| dt.format("%Y-%m-%d %H:%M:%S").to_string())
+}
+
+// Total seconds of a `1w2d3h4m5s`-style duration, None if it doesn't fit in a u64
+pub fn parse_field_duration(raw: &str) -> Option<u64> {
+    if raw.is_empty() { return None; }
+    let caps = DURATION_RE.captures(raw)?;
+    [("w", 604800), ("d", 86400), ("h", 3600), ("m", 60), ("s", 1)].iter().try_fold(0u64, |total, (name, seconds)| {
+        match caps.name(name) {
+            Some(m) => m.as_str().parse::<u64>().ok()?.checked_mul(*seconds)?.checked_add(total),
+            None => Some(total),
+        }
+    })
+}
+
+pub fn parse_field_number(raw: &str) -> Option<f64> {
+    raw.parse::<f64>().ok().filter(|n| n.is_finite())
+}
+
+pub fn is_valid_field_name(name: &str) -> bool {
+    FIELD_NAME_RE.is_match(name)
+}
+
+pub fn is_valid_field_value(value: &str) -> bool {
+    !value.trim().is_empty() && !value.contains(|c: char| c == '(' || c == ')' || c == '\n' || c == '\r')
+}
+
+// Sets (`Some`) or removes (`None`) the first `@name(...)` field in `content`, leaving the rest of the text as is.
+// New fields are appended at the end, before a trailing `-->` or `*/` comment closer.
+pub fn set_field_in_content(content: &str, name: &str, value: Option<&str>) -> String {
+    let existing = FIELD_RE.captures_iter(content).find(|caps| &caps["name"] == name);
+    match (existing, value) {
+        (Some(caps), Some(value)) => {
+            let value_match = caps.name("value").unwrap();
+            format!("{}{}{}", &content[..value_match.start()], value.trim(), &content[value_match.end()..])
+        }
+        (Some(caps), None) => {
+            let whole = caps.get(0).unwrap();
+            let before = content[..whole.start()].trim_end();
+            let after = content[whole.end()..].trim_start();
+            if before.is_empty() || after.is_empty() { format!("{}{}", before, after) } else { format!("{} {}", before, after) }
+        }
+        (None, Some(value)) => {
+            let trimmed = content.trim_end();
+            let body_end = ["-->", "*/"].iter()
+                .find(|closer| trimmed.ends_with(*closer))
+                .map_or(trimmed.len(), |closer| trimmed.len() - closer.len());
+            let body = trimmed[..body_end].trim_end();
+            let closer = &trimmed[body_end..];
+            let field = format!("@{}({})", name, value.trim());
+            let with_field = if body.is_empty() { field } else { format!("{} {}", body, field) };
+            if closer.is_empty() { with_field } else { format!("{} {}", with_field, closer) }
+        }
+        (None, None) => content.to_string(),
+    }
+}
@@ -1,12 +1,22 @@
 #![allow(clippy::all)]
-use crate::config_models::{Config, ProjectConfig};
-use crate::todo_models::{TodoItem, TodoCategoryEnum, TodoCategoryData, ProcessedTodosOutput};
-use crate::utils::{find_git_repo_root, get_char_rank};
+use crate::config_models::{Config, GitCategoryConfig};
+use crate::git_repo::{branch_of, category_repo, normalise_remote_url, worktree_display_name, GitRepoInfo};
+use crate::packages::{nearest_package_dir, package_in_dir, warn_unknown_manifests};
+use crate::project_matching::ProjectMatcher;
+use crate::project_overrides::{file_project_override, PROJECT_DIRECTIVE, PROJECT_DIRECTIVE_MAX_LINE};
+use crate::fingerprint::{fingerprint_file_todos, FingerprintInput};
+use crate::todo_models::{TodoItem, TodoDetails, TodoTreeNode, SubtaskRollup, TodoState, TodoCategoryEnum, TodoCategoryData, ProcessedTodosOutput, ScanDiagnostic, StateCounts};
+use crate::scan_changes::apply_known_fingerprints;
+use crate::time_tracking::{load_time_log, time_spent_by_todo, apply_time_tracking};
+use crate::utils::{get_char_rank, collect_continuation_lines, MAX_CONTINUATION_LINES};
+use crate::todo_parsing::{parse_todo_identity, parse_todo_fields, parse_field_date, field_date_to_timestamp, is_due_date_passed, is_field_date_ahead};
 
 use grep_regex::RegexMatcher;
-use grep_searcher::{Searcher, Sink, SinkMatch};
+use grep_searcher::{Searcher, SearcherBuilder, Sink, SinkContext, SinkFinish, SinkMatch};
 use ignore::WalkBuilder;
 use globset::{Glob, GlobSetBuilder};
+use chrono::{Local, NaiveDateTime};
+use lazy_static::lazy_static;
 use regex::Regex;
 use std::collections::HashMap;
 use std::io;
@@ -15,15 +25,134 @@ use std::sync::Arc;
 use std::time::Instant;
 use parking_lot::Mutex as ParkingMutex;
 
+// --- Ignore directives ---
+// A line containing IGNORE_LINE is skipped entirely, including any other directive on it.
+pub const IGNORE_LINE_DIRECTIVE: &str = "UNITODO_IGNORE_LINE";
+pub const IGNORE_NEXT_LINE_DIRECTIVE: &str = "UNITODO_IGNORE_NEXT_LINE"; // UNITODO_IGNORE_LINE
+pub const IGNORE_START_DIRECTIVE: &str = "UNITODO_IGNORE_START"; // UNITODO_IGNORE_LINE
+pub const IGNORE_END_DIRECTIVE: &str = "UNITODO_IGNORE_END"; // UNITODO_IGNORE_LINE
+pub const IGNORE_FILE_DIRECTIVE: &str = "UNITODO_IGNORE_FILE"; // UNITODO_IGNORE_LINE
+// IGNORE_FILE only counts when it appears within the first N lines of a file
+pub const IGNORE_FILE_DIRECTIVE_MAX_LINE: u64 = 10;
+// Added to the search pattern so directive lines reach the sink even if they hold no todo marker
+const DIRECTIVE_SEARCH_PATTERN: &str = r"UNITODO_(?:IGNORE_(?:NEXT_LINE|START|END|FILE)|PROJECT)";
+
+// A todo found in the current file, kept until `finish` when its continuation lines are known
+#[derive(Debug)]
+pub struct PendingTodo {
+    pub categories: Vec<TodoCategoryEnum>,
+    pub item: TodoItem,
+    pub line_num: u64,
+    pub line: String,
+    pub marker_start: usize,
+}
+
 // --- Sink for grep-searcher ---
+// Todos are buffered per file and only committed in `finish`, so a late directive (e.g. IGNORE_FILE) can still drop them.
+// The searcher runs with after-context, which delivers the lines that may continue a todo to `context`, and one line
+// of before-context for fingerprints.
 #[derive(Debug)]
 pub struct TodoSink {
     pub effective_rg_pattern: String,
     pub grouped_todos: Arc<ParkingMutex<HashMap<TodoCategoryEnum, Vec<TodoItem>>>>,
+    pub diagnostics: Arc<ParkingMutex<Vec<ScanDiagnostic>>>,
     pub current_path: PathBuf,
     pub debug: bool,
     pub start_time: Instant,
-    pub projects: HashMap<String, ProjectConfig>,
+    pub config: Arc<Config>,
+    pub project_matcher: Arc<ProjectMatcher>,
+    pub categories: Option<Vec<TodoCategoryEnum>>, // The current file's, looked up at its first todo
+    pub pending_todos: Vec<PendingTodo>,
+    pub context_lines: HashMap<u64, String>,
+    pub ignore_block_start: Option<u64>,
+    pub ignore_next_line: Option<u64>,
+}
+
+impl TodoSink {
+    pub fn new(
+        effective_rg_pattern: String,
+        grouped_todos: Arc<ParkingMutex<HashMap<TodoCategoryEnum, Vec<TodoItem>>>>,
+        diagnostics: Arc<ParkingMutex<Vec<ScanDiagnostic>>>,
+        current_path: PathBuf,
+        debug: bool,
+        start_time: Instant,
+        config: Arc<Config>,
+        project_matcher: Arc<ProjectMatcher>,
+    ) -> Self {
+        TodoSink {
+            effective_rg_pattern,
+            grouped_todos,
+            diagnostics,
+            current_path,
+            debug,
+            start_time,
+            config,
+            project_matcher,
+            categories: None,
+            pending_todos: Vec::new(),
+            context_lines: HashMap::new(),
+            ignore_block_start: None,
+            ignore_next_line: None,
+        }
+    }
+
+    fn push_diagnostic(&self, line_num: u64, message: String) {
+        if self.debug {
+            eprintln!("[{:.2?}] {}:{}: {}", self.start_time.elapsed(), self.current_path.display(), line_num, message);
+        }
+        self.diagnostics.lock().push(ScanDiagnostic {
+            location: format!("{}:{}", self.current_path.to_string_lossy(), line_num),
+            message,
+        });
+    }
+
+    // Returns Some(continue_search) if the line is a directive (or is suppressed by one) and must not become a todo.
+    fn handle_directives(&mut self, line: &str, line_num: u64) -> Option<bool> {
+        if line.contains(IGNORE_FILE_DIRECTIVE) {
+            if line_num <= IGNORE_FILE_DIRECTIVE_MAX_LINE {
+                if self.debug {
+                    println!("[{:.2?}] Ignoring file {} due to {} on line {}", self.start_time.elapsed(), self.current_path.display(), IGNORE_FILE_DIRECTIVE, line_num);
+                }
+                self.pending_todos.clear();
+                self.ignore_block_start = None;
+                return Some(false);
+            }
+            self.push_diagnostic(line_num, format!("{} must appear within the first {} lines of a file; ignored", IGNORE_FILE_DIRECTIVE, IGNORE_FILE_DIRECTIVE_MAX_LINE));
+            return Some(true);
+        }
+        if line.contains(PROJECT_DIRECTIVE) {
+            // Read with the start of the file when the file's categories are looked up; a later one is a mistake
+            if line_num > PROJECT_DIRECTIVE_MAX_LINE {
+                self.push_diagnostic(line_num, format!("{} must appear within the first {} lines of a file; ignored", PROJECT_DIRECTIVE, PROJECT_DIRECTIVE_MAX_LINE));
+            }
+            return Some(true);
+        }
+        if line.contains(IGNORE_START_DIRECTIVE) {
+            if let Some(open_line) = self.ignore_block_start {
+                self.push_diagnostic(line_num, format!("Nested {} ignored; block already opened on line {}", IGNORE_START_DIRECTIVE, open_line));
+            } else {
+                self.ignore_block_start = Some(line_num);
+            }
+            return Some(true);
+        }
+        if line.contains(IGNORE_END_DIRECTIVE) {
+            if self.ignore_block_start.take().is_none() {
+                self.push_diagnostic(line_num, format!("{} without a preceding {}", IGNORE_END_DIRECTIVE, IGNORE_START_DIRECTIVE));
+            }
+            return Some(true);
+        }
+        if line.contains(IGNORE_NEXT_LINE_DIRECTIVE) {
+            self.ignore_next_line = Some(line_num + 1);
+            return Some(true);
+        }
+        if self.ignore_next_line == Some(line_num) || self.ignore_block_start.is_some() {
+            if self.debug {
+                println!("[{:.2?}] Ignoring TODO on line {} of {} due to an ignore directive", self.start_time.elapsed(), line_num, self.current_path.display()); // UNITODO_IGNORE_LINE
+            }
+            return Some(true);
+        }
+        None
+    }
 }
 
 impl Sink for TodoSink {
@@ -33,7 +162,6 @@ impl Sink for TodoSink {
         let line_bytes = mat.bytes();
         let line_num = mat.line_number().unwrap_or(0);
         let file_path_str = self.current_path.to_string_lossy().to_string();
-        let file_path: &Path = &self.current_path;
 
         let line = match std::str::from_utf8(line_bytes) {
             Ok(s) => s.trim_end(),
@@ -49,7 +177,7 @@ impl Sink for TodoSink {
             }
         };
 
-        if line.contains("UNITODO_IGNORE_LINE") {
+        if line.contains(IGNORE_LINE_DIRECTIVE) {
             if self.debug {
                 println!(
                     "[{:.2?}] Ignoring TODO on line {} of {} due to UNITODO_IGNORE_LINE",
@@ -61,6 +189,11 @@ impl Sink for TodoSink {
             return Ok(true);
         }
 
+        if let Some(continue_search) = self.handle_directives(line, line_num) {
+            return Ok(continue_search);
+        }
+
+        let file_path: &Path = &self.current_path;
         let todo_pattern_re = Regex::new(&self.effective_rg_pattern)
             .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid regex in sink: {}",e)))?;
 
@@ -71,54 +204,326 @@ impl Sink for TodoSink {
             let cleaned_content = content_after_marker.trim_start().to_string();
             
             let location = format!("{}:{}", file_path_str, line_num);
-            let mut category = TodoCategoryEnum::Other;
-            let mut project_match = false;
-
-            for (project_name, project_config) in &self.projects {
-                for pattern_str in &project_config.patterns {
-                    match glob::Pattern::new(pattern_str) {
-                        Ok(pattern) => {
-                            if pattern.matches_path(file_path) { 
-                                category = TodoCategoryEnum::Project(project_name.clone());
-                                project_match = true;
-                                break;
-                            }
-                        }
-                        Err(e) => {
-                             if self.debug {
-                                eprintln!("[Sink] Warning: Invalid glob pattern for project '{}' ('{}'): {}", project_name, pattern_str, e);
-                            }
-                        }
-                    }
-                }
-                if project_match { break; }
-            }
-
-            if !project_match {
-                if let Ok(Some(repo_root_pathbuf)) = find_git_repo_root(file_path) {
-                    if let Some(repo_name_osstr) = repo_root_pathbuf.file_name() {
-                        let repo_name_string = repo_name_osstr.to_string_lossy().into_owned();
-                        category = TodoCategoryEnum::GitRepo(repo_name_string);
-                    } else {
-                        if self.debug {
-                            eprintln!("[{:.2?}] Could not determine repo name from path: {}", self.start_time.elapsed(), repo_root_pathbuf.display());
-                        }
-                    }
-                }
-            }
+            let categories = self.categories.get_or_insert_with(|| categories_for_path(file_path, &self.config, &self.project_matcher)).clone();
 
+            let todo_id = parse_todo_identity(&cleaned_content).id;
             let todo_item = TodoItem {
+                id: todo_id,
+                fields: parse_todo_fields(&cleaned_content),
                 content: cleaned_content,
                 location,
                 status: matched_status_marker,
+                details: None,
+                parent: None,
+                depth: 0,
+                subtasks: None,
+                due: None,
+                scheduled: None,
+                overdue: false,
+                deferred: false,
+                time_spent: 0,
+                estimate: None,
+                timer_started: None,
+                fingerprint: String::new(),
             };
-            let mut todos_map = self.grouped_todos.lock();
-            todos_map.entry(category).or_insert_with(Vec::new).push(todo_item);
+            self.pending_todos.push(PendingTodo {
+                categories,
+                item: todo_item,
+                line_num,
+                line: line.to_string(),
+                marker_start: found_match.start(),
+            });
+        }
+        Ok(true)
+    }
+
+    fn finish(&mut self, _searcher: &Searcher, _finish: &SinkFinish) -> Result<(), io::Error> {
+        if let Some(open_line) = self.ignore_block_start.take() {
+            self.push_diagnostic(open_line, format!("{} has no matching {}; the rest of the file was ignored", IGNORE_START_DIRECTIVE, IGNORE_END_DIRECTIVE));
+        }
+        if self.pending_todos.is_empty() { return Ok(()); }
+
+        let todo_pattern_re = Regex::new(&self.effective_rg_pattern)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid regex in sink: {}",e)))?;
+        for pending in self.pending_todos.iter_mut() {
+            let following_lines = (pending.line_num + 1..)
+                .map_while(|n| self.context_lines.get(&n).map(|l| l.as_str()));
+            let continuation = collect_continuation_lines(&pending.line, pending.marker_start, following_lines, &todo_pattern_re);
+            if !continuation.is_empty() {
+                pending.item.details = Some(TodoDetails {
+                    start_line: pending.line_num + 1,
+                    end_line: pending.line_num + continuation.len() as u64,
+                    text: continuation.join("\n"),
+                });
+            }
+        }
+
+        // Nest checklist items (no comment leader) by indentation. Lines between two todos that are indented no deeper
+        // than an open parent close it; lines the searcher didn't deliver are unknown, so they break the chain.
+        let mut open_parents: Vec<(usize, String)> = Vec::new(); // (indent, location)
+        let mut previous_line_num = 0u64;
+        for pending in self.pending_todos.iter_mut() {
+            for n in previous_line_num + 1..pending.line_num {
+                if open_parents.is_empty() { break; }
+                match self.context_lines.get(&n) {
+                    Some(l) if l.trim().is_empty() => {}
+                    Some(l) => {
+                        let line_indent = l.len() - l.trim_start().len();
+                        while open_parents.last().map_or(false, |(parent_indent, _)| *parent_indent >= line_indent) { open_parents.pop(); }
+                    }
+                    None => open_parents.clear(),
+                }
+            }
+            previous_line_num = pending.line_num;
+
+            let prefix = &pending.line[..pending.marker_start];
+            if !prefix.trim().is_empty() {
+                open_parents.clear();
+                continue;
+            }
+            let indent = prefix.len();
+            while open_parents.last().map_or(false, |(parent_indent, _)| *parent_indent >= indent) { open_parents.pop(); }
+            pending.item.parent = open_parents.last().map(|(_, location)| location.clone());
+            pending.item.depth = open_parents.len() as u32;
+            open_parents.push((indent, pending.item.location.clone()));
+        }
+
+        let file_lines: HashMap<u64, &str> = self.pending_todos.iter().map(|p| (p.line_num, p.line.as_str()))
+            .chain(self.context_lines.iter().map(|(n, l)| (*n, l.as_str())))
+            .collect();
+        let inputs: Vec<FingerprintInput> = self.pending_todos.iter().map(|p| FingerprintInput {
+            content: &p.item.content,
+            previous_line: file_lines.get(&(p.line_num.saturating_sub(1))).copied(),
+            next_line: file_lines.get(&(p.line_num + 1)).copied(),
+        }).collect();
+        let fingerprints = fingerprint_file_todos(&self.current_path.to_string_lossy(), &inputs);
+        for (pending, fingerprint) in self.pending_todos.iter_mut().zip(fingerprints) {
+            pending.item.fingerprint = fingerprint;
+        }
+
+        let mut todos_map = self.grouped_todos.lock();
+        for pending in self.pending_todos.drain(..) {
+            for category in pending.categories {
+                todos_map.entry(category).or_insert_with(Vec::new).push(pending.item.clone());
+            }
+        }
+        Ok(())
+    }
+
+    fn context(&mut self, _searcher: &Searcher, context: &SinkContext<'_>) -> Result<bool, io::Error> {
+        if let (Some(line_num), Ok(line)) = (context.line_number(), std::str::from_utf8(context.bytes())) {
+            self.context_lines.insert(line_num, line.trim_end().to_string());
         }
         Ok(true)
     }
 }
 
+// Categories of a file: the project it names itself, else the first project matching it (every matching one with
+// `multi_project_membership`), else its package if discovery is on, else its git repository, else Other
+pub fn categories_for_path(file_path: &Path, config: &Config, project_matcher: &ProjectMatcher) -> Vec<TodoCategoryEnum> {
+    if let Some(project) = file_project_override(file_path) {
+        // Spelled like a configured project (`ceal` for `Ceal`), it joins that project's category
+        let configured = config.projects.keys().find(|name| name.eq_ignore_ascii_case(&project));
+        return vec![TodoCategoryEnum::Project(configured.cloned().unwrap_or(project))];
+    }
+    let repo = category_repo(file_path, &config.git);
+    let projects = project_matcher.matching_projects(file_path, || {
+        repo.as_ref().map_or_else(Vec::new, |repo| repo.remotes().iter().map(|(_, url)| normalise_remote_url(url)).collect())
+    });
+    if !projects.is_empty() {
+        let count = if config.multi_project_membership { projects.len() } else { 1 };
+        return projects.into_iter().take(count).map(|name| TodoCategoryEnum::Project(name.to_string())).collect();
+    }
+    let repo = match repo { Some(repo) => repo, None => return vec![TodoCategoryEnum::Other] };
+    if let Some(package_dir) = nearest_package_dir(file_path, &repo.root, &config.packages) {
+        return vec![TodoCategoryEnum::Package(package_dir.to_string_lossy().into_owned())];
+    }
+    vec![repo_category(&repo, &config.git)]
+}
+
+// The main category of a single file, outside a scan
+pub fn category_for_path(file_path: &Path, config: &Config, debug: bool) -> TodoCategoryEnum {
+    categories_for_path(file_path, config, &ProjectMatcher::new(config, debug)).into_iter().next().unwrap_or(TodoCategoryEnum::Other)
+}
+
+lazy_static! {
+    // Display names the latest scans gave their categories, by category key
+    static ref SCANNED_CATEGORY_NAMES: ParkingMutex<HashMap<String, String>> = ParkingMutex::new(HashMap::new());
+}
+
+// Name of the category a file's todos are listed under, as the latest scan named it so same-named repositories stay
+// apart; a category no scan has named yet goes by its own name
+pub fn category_name_for_path(file_path: &Path, config: &Config) -> String {
+    let category = category_for_path(file_path, config, false);
+    if let Some(name) = SCANNED_CATEGORY_NAMES.lock().get(&category.key()) { return name.clone(); }
+    match &category {
+        TodoCategoryEnum::Package(dir) => package_in_dir(Path::new(dir.as_str()), &config.packages).unwrap_or_else(|| category.get_details().0),
+        _ => category.get_details().0,
+    }
+}
+
+// A repository's category: its root, or its normalised `origin` URL when clones are grouped by remote
+pub fn repo_category(repo: &GitRepoInfo, git: &GitCategoryConfig) -> TodoCategoryEnum {
+    if git.group_by_remote {
+        if let Some(url) = repo.origin_url() { return TodoCategoryEnum::Remote(normalise_remote_url(&url)); }
+    }
+    TodoCategoryEnum::GitRepo(repo.root.to_string_lossy().into_owned())
+}
+
+// Names repository categories: linked worktrees after their repository if configured, then the closest parent
+// directories (or remote owners) that tell same-named repositories apart (`api (work)`, `api (oss)`), then the
+// branch if configured. `categories` has the category of each name, or None for names that stay as they are; a
+// repository sharing its name only with a project still gets its parent added.
+pub fn name_repo_categories(names: &mut [String], categories: &[Option<&TodoCategoryEnum>], git: &GitCategoryConfig) {
+    let roots: Vec<Option<&Path>> = categories.iter().map(|category| match category {
+        Some(TodoCategoryEnum::GitRepo(root)) => Some(Path::new(root.as_str())),
+        _ => None,
+    }).collect();
+    let paths: Vec<Option<&Path>> = categories.iter().map(|category| match category {
+        Some(TodoCategoryEnum::GitRepo(path)) | Some(TodoCategoryEnum::Remote(path)) | Some(TodoCategoryEnum::Package(path)) => Some(Path::new(path.as_str())),
+        _ => None,
+    }).collect();
+    if git.show_worktree {
+        for (name, root) in names.iter_mut().zip(&roots) {
+            if let Some(worktree_name) = root.and_then(worktree_display_name) { *name = worktree_name; }
+        }
+    }
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for name in names.iter() { *counts.entry(name.clone()).or_insert(0) += 1; }
+    let mut clashing: HashMap<String, Vec<usize>> = HashMap::new();
+    for (index, name) in names.iter().enumerate() {
+        if paths[index].is_some() && counts[name] > 1 { clashing.entry(name.clone()).or_default().push(index); }
+    }
+    for indices in clashing.into_values() {
+        let parents: Vec<Vec<String>> = indices.iter().map(|&i| {
+            let parent = paths[i].and_then(Path::parent).map(Path::to_path_buf).unwrap_or_default();
+            parent.components().rev().map(|c| c.as_os_str().to_string_lossy().into_owned()).filter(|c| c != "/").collect()
+        }).collect();
+        let suffix = |components: &Vec<String>, depth: usize| {
+            components.iter().take(depth).rev().cloned().collect::<Vec<_>>().join("/")
+        };
+        let max_depth = parents.iter().map(Vec::len).max().unwrap_or(0);
+        let depth = (1..=max_depth.max(1)).find(|&depth| {
+            let mut seen = std::collections::HashSet::new();
+            parents.iter().all(|components| seen.insert(suffix(components, depth)))
+        }).unwrap_or(max_depth.max(1));
+        for (&index, components) in indices.iter().zip(&parents) {
+            let suffix = suffix(components, depth);
+            if !suffix.is_empty() { names[index] = format!("{} ({})", names[index], suffix); }
+        }
+    }
+    if git.show_branch {
+        for (name, root) in names.iter_mut().zip(&roots) {
+            if let Some(branch) = root.and_then(branch_of) { *name = format!("{} [{}]", name, branch); }
+        }
+    }
+}
+
+// Orders todos by content using `get_char_rank`, so leading priority words sort 0-9, A-Z, a-z
+pub fn compare_todo_content(a: &TodoItem, b: &TodoItem) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+    let mut a_iter = a.content.chars();
+    let mut b_iter = b.content.chars();
+    loop {
+        match (a_iter.next(), b_iter.next()) {
+            (Some(ca), Some(cb)) => {
+                if ca == cb { continue; }
+                let rank_a = get_char_rank(ca); let rank_b = get_char_rank(cb);
+                if rank_a == rank_b { return ca.cmp(&cb); } else { return rank_a.cmp(&rank_b); }
+            }
+            (Some(_), None) => return Ordering::Greater,
+            (None, Some(_)) => return Ordering::Less,
+            (None, None) => return Ordering::Equal,
+        }
+    }
+}
+
+// Counts each parent's direct children by state (parents always share their children's file, hence category)
+pub fn apply_subtask_rollup(todos: &mut [TodoItem], config: &Config) {
+    let index_by_location: HashMap<String, usize> = todos.iter().enumerate().map(|(i, t)| (t.location.clone(), i)).collect();
+    let mut rollups: HashMap<usize, SubtaskRollup> = HashMap::new();
+    for todo in todos.iter() {
+        let parent_idx = match todo.parent.as_ref().and_then(|p| index_by_location.get(p)) { Some(&i) => i, None => continue };
+        let rollup = rollups.entry(parent_idx).or_insert(SubtaskRollup { total: 0, done: 0 });
+        match config.todo_state_of(&todo.status) {
+            Some(TodoState::Cancelled) => {}
+            Some(TodoState::Done) => { rollup.total += 1; rollup.done += 1; }
+            _ => rollup.total += 1,
+        }
+    }
+    for (idx, rollup) in rollups {
+        todos[idx].subtasks = Some(rollup);
+    }
+}
+
+// Resolves `@due`/`@scheduled` fields and flags open todos whose due date passed by `now` (local time),
+// or whose `@defer` date is still ahead
+pub fn apply_due_dates(todos: &mut [TodoItem], config: &Config, now: &NaiveDateTime) {
+    for todo in todos.iter_mut() {
+        let field_timestamp = |name: &str| todo.fields.get(name).and_then(|f| parse_field_date(&f.raw)).as_ref().and_then(field_date_to_timestamp);
+        let (due, scheduled) = (field_timestamp("due"), field_timestamp("scheduled"));
+        let is_open = !config.todo_state_of(&todo.status).map_or(false, |s| s.is_closed());
+        todo.overdue = is_open && todo.fields.get("due").map_or(false, |f| is_due_date_passed(&f.raw, now));
+        todo.deferred = is_open && todo.fields.get("defer").map_or(false, |f| is_field_date_ahead(&f.raw, now));
+        todo.due = due;
+        todo.scheduled = scheduled;
+    }
+}
+
+// A category with its deferred and per-state counts filled in from `todos`
+pub fn summarize_category(key: String, name: String, icon: String, todos: Vec<TodoItem>, config: &Config) -> TodoCategoryData {
+    let mut state_counts = StateCounts::default();
+    for todo in &todos {
+        match config.todo_state_of(&todo.status) {
+            Some(TodoState::Open) => state_counts.open += 1,
+            Some(TodoState::Active) => state_counts.active += 1,
+            Some(TodoState::Done) => state_counts.done += 1,
+            Some(TodoState::Cancelled) => state_counts.cancelled += 1,
+            None => {}
+        }
+    }
+    let deferred_count = todos.iter().filter(|t| t.deferred).count() as u32;
+    TodoCategoryData { key, name, icon, todos, deferred_count, state_counts, parent_key: None, parent_name: None }
+}
+
+// Drops deferred todos from every category; `deferred_count` keeps recording how many there are
+pub fn remove_deferred_todos(categories: &mut [TodoCategoryData]) {
+    for category in categories.iter_mut() {
+        category.todos.retain(|t| !t.deferred);
+    }
+}
+
+// Nests a category's (already sorted) todos under their parents; items whose parent isn't in the list become roots
+pub fn build_todo_tree(todos: &[TodoItem]) -> Vec<TodoTreeNode> {
+    let index_by_location: HashMap<&str, usize> = todos.iter().enumerate().map(|(i, t)| (t.location.as_str(), i)).collect();
+    let mut children_of: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut roots: Vec<usize> = Vec::new();
+    for (i, todo) in todos.iter().enumerate() {
+        match todo.parent.as_deref().and_then(|p| index_by_location.get(p)) {
+            Some(&parent_idx) => children_of.entry(parent_idx).or_insert_with(Vec::new).push(i),
+            None => roots.push(i),
+        }
+    }
+    fn build_node(idx: usize, todos: &[TodoItem], children_of: &HashMap<usize, Vec<usize>>) -> TodoTreeNode {
+        TodoTreeNode {
+            item: todos[idx].clone(),
+            children: children_of.get(&idx).map_or_else(Vec::new, |children| {
+                children.iter().map(|&child| build_node(child, todos, children_of)).collect()
+            }),
+        }
+    }
+    roots.into_iter().map(|idx| build_node(idx, todos, &children_of)).collect()
+}
+
+// The path a walk error is about, if any: a directory or file that couldn't be read
+fn walk_error_path(err: &ignore::Error) -> Option<&Path> {
+    match err {
+        ignore::Error::WithPath { path, .. } => Some(path),
+        ignore::Error::WithDepth { err, .. } | ignore::Error::WithLineNumber { err, .. } => walk_error_path(err),
+        _ => None,
+    }
+}
+
 // --- Core todo Finding Logic --- (Accepts &Config from active profile)
 pub fn find_and_process_todos(active_profile_config: &Config, debug: bool) -> io::Result<ProcessedTodosOutput> {
     let start_time = Instant::now();
@@ -131,13 +536,14 @@ pub fn find_and_process_todos(active_profile_config: &Config, debug: bool) -> io
                 start_time.elapsed()
             );
         }
-        return Ok(ProcessedTodosOutput { categories: Vec::new() });
+        return Ok(ProcessedTodosOutput { categories: Vec::new(), diagnostics: Vec::new(), unscanned_paths: Vec::new() });
     }
 
     let effective_pattern = active_profile_config.get_effective_rg_pattern();
     if debug { println!("[{:.2?}] Using effective search pattern: {}", start_time.elapsed(), effective_pattern); }
 
-    let matcher = RegexMatcher::new(&effective_pattern)
+    let search_pattern = format!("{}|{}", effective_pattern, DIRECTIVE_SEARCH_PATTERN);
+    let matcher = RegexMatcher::new(&search_pattern)
         .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid regex from active profile: {}",e)))?;
 
     let mut custom_ignore_builder = GlobSetBuilder::new();
@@ -159,37 +565,56 @@ pub fn find_and_process_todos(active_profile_config: &Config, debug: bool) -> io
     if let Some(types) = &active_profile_config.rg.file_types {
         if debug { eprintln!("Warning: config `rg.file_types` ('{:?}') not supported with internal search.", types); }
     }
+    if active_profile_config.packages.enabled { warn_unknown_manifests(&active_profile_config.packages); }
 
     let search_start_time = Instant::now();
     let grouped_todos = Arc::new(ParkingMutex::new(HashMap::<TodoCategoryEnum, Vec<TodoItem>>::new()));
-    let sink_projects_clone = active_profile_config.projects.clone();
+    let diagnostics = Arc::new(ParkingMutex::new(Vec::<ScanDiagnostic>::new()));
+    // A root that is missing (say, an unmounted drive) is reported like a file that failed to read
+    let unscanned_paths = Arc::new(ParkingMutex::new(
+        active_profile_config.rg.paths.iter().filter(|root| !Path::new(root.as_str()).exists()).cloned().collect::<Vec<String>>()
+    ));
+    let sink_config = Arc::new(active_profile_config.clone());
+    let project_matcher = Arc::new(ProjectMatcher::new(active_profile_config, debug));
 
     builder.build_parallel().run(|| {
         let current_matcher_clone = matcher.clone();
         let current_todos_arc_clone = Arc::clone(&grouped_todos);
+        let current_diagnostics_arc_clone = Arc::clone(&diagnostics);
+        let unscanned_paths_clone = Arc::clone(&unscanned_paths);
         let sink_effective_pattern_clone = effective_pattern.clone();
-        let sink_projects_closure_clone = sink_projects_clone.clone();
+        let sink_config_clone = Arc::clone(&sink_config);
+        let project_matcher_clone = Arc::clone(&project_matcher);
         let current_custom_ignores_arc_clone = Arc::clone(&custom_ignores);
         let closure_debug = debug;
         let closure_start_time = start_time;
 
         Box::new(move |result| {
-            let entry = match result { Ok(e) => e, Err(_) => return ignore::WalkState::Continue };
+            let entry = match result {
+                Ok(e) => e,
+                Err(err) => {
+                    if let Some(path) = walk_error_path(&err) { unscanned_paths_clone.lock().push(path.to_string_lossy().into_owned()); }
+                    return ignore::WalkState::Continue;
+                }
+            };
             let path = entry.path();
             if current_custom_ignores_arc_clone.is_match(path) { return ignore::WalkState::Continue; }
             
             if entry.file_type().map_or(false, |ft| ft.is_file()) {
-                let mut searcher = Searcher::new();
-                let mut sink = TodoSink {
-                    effective_rg_pattern: sink_effective_pattern_clone.clone(),
-                    grouped_todos: Arc::clone(&current_todos_arc_clone),
-                    current_path: path.to_path_buf(),
-                    debug: closure_debug,
-                    start_time: closure_start_time, 
-                    projects: sink_projects_closure_clone.clone(),
-                };
+                let mut searcher = SearcherBuilder::new().before_context(1).after_context(MAX_CONTINUATION_LINES).build();
+                let mut sink = TodoSink::new(
+                    sink_effective_pattern_clone.clone(),
+                    Arc::clone(&current_todos_arc_clone),
+                    Arc::clone(&current_diagnostics_arc_clone),
+                    path.to_path_buf(),
+                    closure_debug,
+                    closure_start_time,
+                    Arc::clone(&sink_config_clone),
+                    Arc::clone(&project_matcher_clone),
+                );
                 if let Err(err) = searcher.search_path(&current_matcher_clone, path, &mut sink) {
                     if closure_debug { eprintln!("[{:.2?}] Error searching {}: {}", closure_start_time.elapsed(), path.display(), err); }
+                    unscanned_paths_clone.lock().push(path.to_string_lossy().into_owned());
                 }
             }
             ignore::WalkState::Continue
@@ -203,34 +628,70 @@ pub fn find_and_process_todos(active_profile_config: &Config, debug: bool) -> io
         Ok(mutex) => mutex.into_inner(),
         Err(_) => return Err(io::Error::new(io::ErrorKind::Other, "Mutex unwrap error after parallel walk")),
     };
+    let mut final_diagnostics = match Arc::try_unwrap(diagnostics) {
+        Ok(mutex) => mutex.into_inner(),
+        Err(_) => return Err(io::Error::new(io::ErrorKind::Other, "Mutex unwrap error for diagnostics after parallel walk")),
+    };
+    let mut unscanned_paths = std::mem::take(&mut *unscanned_paths.lock());
+    unscanned_paths.sort();
+    unscanned_paths.dedup();
+    for path in &unscanned_paths { log::warn!("Could not scan {}; its todos are left as they were", path); }
+    final_diagnostics.sort_by_key(|d| {
+        let (file, line) = d.location.rsplit_once(':').unwrap_or((d.location.as_str(), "0"));
+        (file.to_string(), line.parse::<u64>().unwrap_or(0))
+    });
+    let git_config = &active_profile_config.git;
     let mut categories_keys: Vec<TodoCategoryEnum> = final_grouped_todos.keys().cloned().collect();
-    categories_keys.sort();
+    // A package's repository is named along with the scan's categories, whether or not it has todos of its own
+    let package_parents: HashMap<TodoCategoryEnum, TodoCategoryEnum> = categories_keys.iter().filter_map(|key| match key {
+        TodoCategoryEnum::Package(dir) => category_repo(Path::new(dir.as_str()), git_config).map(|repo| (key.clone(), repo_category(&repo, git_config))),
+        _ => None,
+    }).collect();
+    let mut named_keys = categories_keys.clone();
+    for parent in package_parents.values() {
+        if !named_keys.contains(parent) { named_keys.push(parent.clone()); }
+    }
+    let mut names: Vec<String> = named_keys.iter().map(|key| match key {
+        TodoCategoryEnum::Package(dir) => package_in_dir(Path::new(dir.as_str()), &active_profile_config.packages).unwrap_or_else(|| key.get_details().0),
+        _ => key.get_details().0,
+    }).collect();
+    name_repo_categories(&mut names, &named_keys.iter().map(Some).collect::<Vec<_>>(), git_config);
+    let category_names: HashMap<TodoCategoryEnum, String> = named_keys.into_iter().zip(names).collect();
+    SCANNED_CATEGORY_NAMES.lock().extend(category_names.iter().map(|(key, name)| (key.key(), name.clone())));
+    // Projects, then repositories each followed by its packages, then Other, each by display name
+    categories_keys.sort_by_cached_key(|key| {
+        let name = category_names[key].clone();
+        match key {
+            TodoCategoryEnum::Project(_) => (0, name, String::new()),
+            TodoCategoryEnum::Package(_) => (1, package_parents.get(key).map_or_else(|| name.clone(), |parent| category_names[parent].clone()), name),
+            TodoCategoryEnum::GitRepo(_) | TodoCategoryEnum::Remote(_) => (1, name, String::new()),
+            TodoCategoryEnum::Other => (2, name, String::new()),
+        }
+    });
+    let now = Local::now().naive_local();
+    let time_spent = load_time_log()
+        .map(|log| time_spent_by_todo(&log, Local::now().timestamp()))
+        .unwrap_or_else(|e| { log::warn!("Failed to read the time log: {}", e); HashMap::new() });
     
-    let output_categories: Vec<TodoCategoryData> = categories_keys.into_iter().filter_map(|key| {
+    let mut output_categories: Vec<TodoCategoryData> = categories_keys.into_iter().filter_map(|key| {
         final_grouped_todos.get(&key).map(|todos_vec| {
             let mut sorted_todos = todos_vec.clone();
-            sorted_todos.sort_by(|a, b| { 
-                use std::cmp::Ordering;
-                let mut a_iter = a.content.chars();
-                let mut b_iter = b.content.chars();
-                loop {
-                    match (a_iter.next(), b_iter.next()) {
-                        (Some(ca), Some(cb)) => {
-                            if ca == cb { continue; }
-                            let rank_a = get_char_rank(ca); let rank_b = get_char_rank(cb);
-                            if rank_a == rank_b { return ca.cmp(&cb); } else { return rank_a.cmp(&rank_b); }
-                        }
-                        (Some(_), None) => return Ordering::Greater, 
-                        (None, Some(_)) => return Ordering::Less,
-                        (None, None) => return Ordering::Equal,
-                    }
-                }
-            });
-            let (name, icon) = key.get_details();
-            TodoCategoryData { name, icon, todos: sorted_todos }
+            sorted_todos.sort_by(compare_todo_content);
+            apply_subtask_rollup(&mut sorted_todos, active_profile_config);
+            apply_due_dates(&mut sorted_todos, active_profile_config, &now);
+            apply_time_tracking(&mut sorted_todos, &time_spent);
+            let icon = key.get_details().1;
+            let mut category = summarize_category(key.key(), category_names[&key].clone(), icon, sorted_todos, active_profile_config);
+            if let Some(parent) = package_parents.get(&key) {
+                category.parent_key = Some(parent.key());
+                category.parent_name = Some(category_names[parent].clone());
+            }
+            category
         })
     }).collect();
 
+    apply_known_fingerprints(active_profile_config, &mut output_categories);
+
     if debug { println!("[{:.2?}] Output processed in {:.2?}. Total: {:.2?}", start_time.elapsed(), format_output_start.elapsed(), start_time.elapsed()); }
-    Ok(ProcessedTodosOutput { categories: output_categories })
+    Ok(ProcessedTodosOutput { categories: output_categories, diagnostics: final_diagnostics, unscanned_paths })
 } 
\ No newline at end of file
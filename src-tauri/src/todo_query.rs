@@ -0,0 +1,337 @@
+#![allow(clippy::all)]
+use crate::config_models::Config;
+use crate::natural_dates::parse_natural_date;
+use crate::todo_models::{ProcessedTodosOutput, QueryMatch, TextRange, TodoItem, TodoState};
+use crate::todo_parsing::{is_field_date_only, parse_field_date, parse_field_duration, parse_todo_identity};
+
+use chrono::{Duration, NaiveDateTime};
+use globset::{GlobBuilder, GlobMatcher};
+use regex::Regex;
+use std::io;
+
+// --- Todo queries, shared by the QueryTodos RPC and the `query` command ---
+// A query is a list of terms that must all hold: `state:open prio:<=1 repo:Unitodo text:"login" due:<7d -path:**/vendor/**`.
+// `key:value` terms filter on one property, a leading `-` negates a term, and a bare word or "quoted phrase" searches
+// the text. Comma-separated values match any of them (`state:open,active`). Relative dates are resolved at parse time.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TodoFlag {
+    Overdue,
+    Deferred,
+    Running, // A timer is running
+    Subtask, // Nested under another checklist item
+    Parent,  // Has nested checklist items
+}
+
+#[derive(Debug, Clone)]
+pub enum PathPattern {
+    Glob(GlobMatcher),
+    Substring(String),
+}
+
+#[derive(Debug, Clone)]
+pub enum QueryPredicate {
+    State(Vec<TodoState>),
+    Priority(Option<(Comparison, u64)>), // None: has any priority
+    Category(Vec<String>),               // Lowercased names
+    Text(Regex),                         // Case-insensitive literal
+    Path(Vec<PathPattern>),
+    Id(Option<Vec<String>>), // None: has any id
+    Date { field: String, comparison: Comparison, start: NaiveDateTime, end: NaiveDateTime }, // The value names [start, end)
+    HasField(String),
+    Is(TodoFlag),
+}
+
+#[derive(Debug, Clone)]
+pub struct QueryTerm {
+    pub negated: bool,
+    pub predicate: QueryPredicate,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TodoQuery {
+    pub terms: Vec<QueryTerm>,
+}
+
+fn invalid_query(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+// Splits a query at unquoted whitespace, dropping the quotes. Each token comes with the byte offset of its first
+// unquoted `:` in the unquoted text, if any, so `"a:b"` stays a text search.
+fn tokenize(text: &str) -> io::Result<Vec<(String, Option<usize>)>> {
+    let mut tokens = Vec::new();
+    let mut current: Option<(String, Option<usize>)> = None;
+    let mut in_quotes = false;
+    for c in text.chars() {
+        if c.is_whitespace() && !in_quotes {
+            tokens.extend(current.take());
+            continue;
+        }
+        let (token, colon) = current.get_or_insert_with(|| (String::new(), None));
+        match c {
+            '"' => in_quotes = !in_quotes,
+            ':' if !in_quotes && colon.is_none() => { *colon = Some(token.len()); token.push(c); }
+            _ => token.push(c),
+        }
+    }
+    if in_quotes { return Err(invalid_query("Unterminated quote in query".to_string())); }
+    tokens.extend(current);
+    Ok(tokens)
+}
+
+fn split_comparison(value: &str) -> (Comparison, &str) {
+    for (prefix, comparison) in [("<=", Comparison::LessOrEqual), (">=", Comparison::GreaterOrEqual), ("<", Comparison::Less), (">", Comparison::Greater), ("=", Comparison::Equal)] {
+        if let Some(rest) = value.strip_prefix(prefix) { return (comparison, rest); }
+    }
+    (Comparison::Equal, value)
+}
+
+// Leading number of a priority word: `0` for `0`, `01` and `0-1`
+fn priority_rank(priority: &str) -> Option<u64> {
+    let digits: String = priority.chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok()
+}
+
+// The span of time a date value names: a whole day for dates, a moment for times and offsets from now (`7d`, `-2w`)
+fn parse_date_value(key: &str, value: &str, now: &NaiveDateTime) -> io::Result<(NaiveDateTime, NaiveDateTime)> {
+    let out_of_range = || invalid_query(format!("Date '{}' for '{}:' is out of range", value, key));
+    let (sign, magnitude) = match value.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let (start, length) = match parse_field_duration(magnitude) {
+        Some(seconds) => {
+            let offset = i64::try_from(seconds).ok().and_then(|s| Duration::try_seconds(sign * s)).ok_or_else(out_of_range)?;
+            (now.checked_add_signed(offset).ok_or_else(out_of_range)?, Duration::seconds(1))
+        }
+        None => {
+            let raw = parse_natural_date(value, now).unwrap_or_else(|| value.to_string());
+            let start = parse_field_date(&raw).ok_or_else(|| invalid_query(format!("Invalid date '{}' for '{}:'", value, key)))?;
+            (start, if is_field_date_only(&raw) { Duration::days(1) } else { Duration::seconds(1) })
+        }
+    };
+    Ok((start, start.checked_add_signed(length).ok_or_else(out_of_range)?))
+}
+
+fn date_field_name(key: &str) -> Option<&'static str> {
+    match key {
+        "due" => Some("due"),
+        "scheduled" => Some("scheduled"),
+        "defer" => Some("defer"),
+        "created" => Some("created"),
+        "done" | "finished" => Some("finished"),
+        _ => None,
+    }
+}
+
+fn parse_term(key: &str, value: &str, now: &NaiveDateTime) -> io::Result<(bool, QueryPredicate)> {
+    let values = || value.split(',').map(str::trim).filter(|v| !v.is_empty());
+    if value.is_empty() { return Err(invalid_query(format!("Missing value for '{}:'", key))); }
+    let predicate = match key {
+        "state" | "status" => QueryPredicate::State(values().map(|v| match v.to_lowercase().as_str() {
+            "open" | "todo" => Ok(vec![TodoState::Open]),
+            "active" | "doing" => Ok(vec![TodoState::Active]),
+            "done" => Ok(vec![TodoState::Done]),
+            "cancelled" | "canceled" => Ok(vec![TodoState::Cancelled]),
+            "closed" => Ok(vec![TodoState::Done, TodoState::Cancelled]),
+            other => Err(invalid_query(format!("Unknown state '{}' (expected open, active, done, cancelled or closed)", other))),
+        }).collect::<io::Result<Vec<_>>>()?.concat()),
+        "prio" | "priority" => match value {
+            "any" => QueryPredicate::Priority(None),
+            "none" => return Ok((true, QueryPredicate::Priority(None))),
+            _ => {
+                let (comparison, number) = split_comparison(value);
+                let rank = number.parse().map_err(|_| invalid_query(format!("Invalid priority '{}'", value)))?;
+                QueryPredicate::Priority(Some((comparison, rank)))
+            }
+        },
+        "repo" | "project" | "category" | "cat" => QueryPredicate::Category(values().map(str::to_lowercase).collect()),
+        "text" => QueryPredicate::Text(Regex::new(&format!("(?i){}", regex::escape(value))).map_err(|e| invalid_query(e.to_string()))?),
+        "path" | "file" => QueryPredicate::Path(values().map(|v| {
+            if !v.contains(|c| matches!(c, '*' | '?' | '[' | '{')) { return Ok(PathPattern::Substring(v.to_string())); }
+            GlobBuilder::new(v).literal_separator(true).build().map(|glob| PathPattern::Glob(glob.compile_matcher()))
+                .map_err(|e| invalid_query(format!("Invalid path pattern '{}': {}", v, e)))
+        }).collect::<io::Result<Vec<_>>>()?),
+        "id" => match value {
+            "any" => QueryPredicate::Id(None),
+            "none" => return Ok((true, QueryPredicate::Id(None))),
+            _ => QueryPredicate::Id(Some(values().map(str::to_string).collect())),
+        },
+        "has" => QueryPredicate::HasField(value.trim_start_matches('@').to_string()),
+        "is" => QueryPredicate::Is(match value.to_lowercase().as_str() {
+            "overdue" => TodoFlag::Overdue,
+            "deferred" => TodoFlag::Deferred,
+            "running" => TodoFlag::Running,
+            "subtask" => TodoFlag::Subtask,
+            "parent" => TodoFlag::Parent,
+            other => return Err(invalid_query(format!("Unknown flag '{}' (expected overdue, deferred, running, subtask or parent)", other))),
+        }),
+        _ => match date_field_name(key) {
+            Some(field) if value == "any" => QueryPredicate::HasField(field.to_string()),
+            Some(field) if value == "none" => return Ok((true, QueryPredicate::HasField(field.to_string()))),
+            Some(field) => {
+                let (comparison, date) = split_comparison(value);
+                let (start, end) = parse_date_value(key, date, now)?;
+                QueryPredicate::Date { field: field.to_string(), comparison, start, end }
+            }
+            None => return Err(invalid_query(format!("Unknown query key '{}'", key))),
+        },
+    };
+    Ok((false, predicate))
+}
+
+// Parses a query, resolving relative dates against `now` (local time). An empty query matches every todo.
+pub fn parse_query(text: &str, now: &NaiveDateTime) -> io::Result<TodoQuery> {
+    let mut query = TodoQuery::default();
+    for (token, colon) in tokenize(text)? {
+        let (negated, body, colon) = match token.strip_prefix('-') {
+            Some(rest) if !rest.is_empty() => (true, rest, colon.map(|c| c - 1)),
+            _ => (false, token.as_str(), colon),
+        };
+        let (inverted, predicate) = match colon {
+            Some(c) if c > 0 => parse_term(&body[..c].to_lowercase(), &body[c + 1..], now)?,
+            _ => (false, QueryPredicate::Text(Regex::new(&format!("(?i){}", regex::escape(body))).map_err(|e| invalid_query(e.to_string()))?)),
+        };
+        query.terms.push(QueryTerm { negated: negated != inverted, predicate });
+    }
+    Ok(query)
+}
+
+impl QueryPredicate {
+    fn matches(&self, config: &Config, category: &str, item: &TodoItem) -> bool {
+        match self {
+            QueryPredicate::State(states) => config.todo_state_of(&item.status).map_or(false, |s| states.contains(&s)),
+            QueryPredicate::Priority(bound) => {
+                let Some(rank) = parse_todo_identity(&item.content).priority.as_deref().and_then(priority_rank) else { return false };
+                bound.map_or(true, |(comparison, value)| match comparison {
+                    Comparison::Less => rank < value,
+                    Comparison::LessOrEqual => rank <= value,
+                    Comparison::Equal => rank == value,
+                    Comparison::GreaterOrEqual => rank >= value,
+                    Comparison::Greater => rank > value,
+                })
+            }
+            QueryPredicate::Category(names) => names.contains(&category.to_lowercase()),
+            QueryPredicate::Text(re) => re.is_match(&item.content),
+            QueryPredicate::Path(patterns) => {
+                let file = item.location.rsplit_once(':').map_or(item.location.as_str(), |(file, _)| file);
+                patterns.iter().any(|p| match p {
+                    PathPattern::Glob(glob) => glob.is_match(file),
+                    PathPattern::Substring(s) => file.contains(s.as_str()),
+                })
+            }
+            QueryPredicate::Id(ids) => match (&item.id, ids) {
+                (Some(_), None) => true,
+                (Some(id), Some(ids)) => ids.iter().any(|i| i == id),
+                (None, _) => false,
+            },
+            QueryPredicate::Date { field, comparison, start, end } => {
+                let Some(date) = item.fields.get(field).and_then(|f| parse_field_date(&f.raw)) else { return false };
+                match comparison {
+                    Comparison::Less => date < *start,
+                    Comparison::LessOrEqual => date < *end,
+                    Comparison::Equal => date >= *start && date < *end,
+                    Comparison::GreaterOrEqual => date >= *start,
+                    Comparison::Greater => date >= *end,
+                }
+            }
+            QueryPredicate::HasField(name) => item.fields.contains_key(name),
+            QueryPredicate::Is(flag) => match flag {
+                TodoFlag::Overdue => item.overdue,
+                TodoFlag::Deferred => item.deferred,
+                TodoFlag::Running => item.timer_started.is_some(),
+                TodoFlag::Subtask => item.parent.is_some(),
+                TodoFlag::Parent => item.subtasks.is_some(),
+            },
+        }
+    }
+}
+
+impl TodoQuery {
+    pub fn matches(&self, config: &Config, category: &str, item: &TodoItem) -> bool {
+        self.terms.iter().all(|term| term.predicate.matches(config, category, item) != term.negated)
+    }
+
+    // Whether the query asks for deferred todos, which are otherwise left out
+    pub fn asks_for_deferred(&self) -> bool {
+        self.terms.iter().any(|term| !term.negated && matches!(term.predicate, QueryPredicate::Is(TodoFlag::Deferred)))
+    }
+
+    // Spans of `content` matched by the query's text terms, in order and merged where they overlap
+    pub fn highlights(&self, content: &str) -> Vec<TextRange> {
+        let mut spans: Vec<(usize, usize)> = self.terms.iter()
+            .filter(|term| !term.negated)
+            .filter_map(|term| match &term.predicate { QueryPredicate::Text(re) => Some(re), _ => None })
+            .flat_map(|re| re.find_iter(content).map(|m| (m.start(), m.end())))
+            .collect();
+        spans.sort();
+        let mut merged: Vec<(usize, usize)> = Vec::new();
+        for (start, end) in spans {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        let utf16_offset = |byte: usize| content[..byte].encode_utf16().count() as u32;
+        merged.into_iter().map(|(start, end)| TextRange { start: utf16_offset(start), end: utf16_offset(end) }).collect()
+    }
+}
+
+// Todos of a scan matching `query`, in scan order
+pub fn query_todos(processed: &ProcessedTodosOutput, config: &Config, query: &TodoQuery) -> Vec<QueryMatch> {
+    processed.categories.iter().flat_map(|category| {
+        category.todos.iter()
+            .filter(|item| query.matches(config, &category.name, item))
+            .map(|item| QueryMatch { item: item.clone(), category: category.name.clone(), highlights: query.highlights(&item.content) })
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn now() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 5, 10).unwrap().and_hms_opt(12, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn offsets_resolve_from_now() {
+        let (start, end) = parse_date_value("due", "7d", &now()).unwrap();
+        assert_eq!(start, now() + Duration::days(7));
+        assert_eq!(end, start + Duration::seconds(1));
+        assert_eq!(parse_date_value("due", "-2w", &now()).unwrap().0, now() - Duration::weeks(2));
+    }
+
+    #[test]
+    fn dates_span_the_whole_day() {
+        let (start, end) = parse_date_value("due", "2024-06-01", &now()).unwrap();
+        assert_eq!(start, NaiveDate::from_ymd_opt(2024, 6, 1).unwrap().and_hms_opt(0, 0, 0).unwrap());
+        assert_eq!(end, start + Duration::days(1));
+    }
+
+    #[test]
+    fn overflowing_offsets_are_invalid_queries() {
+        for value in ["18446744073709551615s", "9223372036854775807s", "-9223372036854775807s", "100000000w"] {
+            let error = parse_date_value("due", value, &now()).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput, "{}", value);
+            assert!(error.to_string().contains("out of range"), "{}: {}", value, error);
+        }
+    }
+
+    #[test]
+    fn overflowing_offsets_fail_the_query() {
+        assert!(parse_query("due:<9223372036854775807s", &now()).is_err());
+        assert!(parse_query("due:<7d", &now()).is_ok());
+    }
+}
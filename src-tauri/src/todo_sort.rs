@@ -0,0 +1,129 @@
+#![allow(clippy::all)]
+use crate::todo_models::{QueryMatch, TodoCategoryData, TodoItem};
+use crate::todo_parsing::{field_date_to_timestamp, is_priority_like, parse_field_date, parse_todo_identity};
+use crate::todo_processing::compare_todo_content;
+use crate::utils::decode_short_timestamp;
+
+use std::cmp::Ordering;
+use std::io;
+
+// --- Declarative sorting for requests and views ---
+// A sort spec is a list of keys, most significant first, each written `key`, `key desc` or `-key`. Todos missing a
+// key's value (no priority, no due date) come after the others in either direction, and ties keep the scan order.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortField {
+    Priority,     // The priority word in natural order: 0 < 1 < 2 < 10, 1 < 1-2
+    PriorityRank, // The whole content by character rank, the default order of a category
+    Created,      // `@created(...)`, or the time in a short-timestamp id
+    Due,
+    Done,         // `@finished(...)`, or the time in a `@@` done stamp
+    File,
+    Line,
+    Category,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortKey {
+    pub field: SortField,
+    pub descending: bool,
+}
+
+fn parse_sort_key(text: &str) -> io::Result<SortKey> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+    let text = text.trim();
+    let (text, negated) = match text.strip_prefix('-') { Some(rest) => (rest, true), None => (text, false) };
+    let mut words = text.split_whitespace();
+    let name = words.next().ok_or_else(|| invalid("Empty sort key".to_string()))?;
+    let descending = match (words.next().map(str::to_lowercase).as_deref(), words.next()) {
+        (None, _) => negated,
+        (Some("asc"), None) => negated,
+        (Some("desc"), None) => !negated,
+        _ => return Err(invalid(format!("Invalid sort key '{}' (expected '<key> asc' or '<key> desc')", text))),
+    };
+    let field = match name.to_lowercase().as_str() {
+        "priority" | "prio" => SortField::Priority,
+        "priority-rank" | "rank" => SortField::PriorityRank,
+        "created" => SortField::Created,
+        "due" => SortField::Due,
+        "done" | "finished" => SortField::Done,
+        "file" | "path" => SortField::File,
+        "line" => SortField::Line,
+        "category" | "repo" | "project" => SortField::Category,
+        other => return Err(invalid(format!("Unknown sort key '{}' (expected priority, priority-rank, created, due, done, file, line or category)", other))),
+    };
+    Ok(SortKey { field, descending })
+}
+
+pub fn parse_sort_spec(keys: &[String]) -> io::Result<Vec<SortKey>> {
+    keys.iter().map(|key| parse_sort_key(key)).collect()
+}
+
+fn field_timestamp(item: &TodoItem, name: &str) -> Option<i64> {
+    item.fields.get(name).and_then(|f| parse_field_date(&f.raw)).and_then(|date| field_date_to_timestamp(&date))
+}
+
+fn created_at(item: &TodoItem) -> Option<i64> {
+    field_timestamp(item, "created").or_else(|| {
+        item.id.as_deref().and_then(|id| id.strip_prefix('@')).and_then(decode_short_timestamp).map(|t| t as i64)
+    })
+}
+
+fn done_at(item: &TodoItem) -> Option<i64> {
+    field_timestamp(item, "finished").or_else(|| {
+        parse_todo_identity(&item.content).done_stamp.as_deref().and_then(decode_short_timestamp).map(|t| t as i64)
+    })
+}
+
+fn file_and_line(item: &TodoItem) -> (&str, Option<u64>) {
+    match item.location.rsplit_once(':') {
+        Some((file, line)) => (file, line.parse().ok()),
+        None => (item.location.as_str(), None),
+    }
+}
+
+// Orders present values by direction and puts missing ones last
+fn compare_present<T>(a: Option<T>, b: Option<T>, descending: bool, compare: impl FnOnce(&T, &T) -> Ordering) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => if descending { compare(&b, &a) } else { compare(&a, &b) },
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+// Compares two todos, each with the name of its category, by a sort spec
+pub fn compare_by_sort_keys(keys: &[SortKey], a: (&TodoItem, &str), b: (&TodoItem, &str)) -> Ordering {
+    let ((a_item, a_category), (b_item, b_category)) = (a, b);
+    for key in keys {
+        let ordering = match key.field {
+            SortField::Priority => {
+                let priority = |item: &TodoItem| parse_todo_identity(&item.content).priority.filter(|p| is_priority_like(p));
+                compare_present(priority(a_item), priority(b_item), key.descending, |a, b| natord::compare(a, b))
+            }
+            SortField::PriorityRank => compare_present(Some(a_item), Some(b_item), key.descending, |a, b| compare_todo_content(a, b)),
+            SortField::Created => compare_present(created_at(a_item), created_at(b_item), key.descending, Ord::cmp),
+            SortField::Due => compare_present(a_item.due, b_item.due, key.descending, Ord::cmp),
+            SortField::Done => compare_present(done_at(a_item), done_at(b_item), key.descending, Ord::cmp),
+            SortField::File => compare_present(Some(file_and_line(a_item).0), Some(file_and_line(b_item).0), key.descending, |a, b| natord::compare(a, b)),
+            SortField::Line => compare_present(file_and_line(a_item).1, file_and_line(b_item).1, key.descending, Ord::cmp),
+            SortField::Category => compare_present(Some(a_category), Some(b_category), key.descending, |a, b| natord::compare(a, b)),
+        };
+        if ordering != Ordering::Equal { return ordering; }
+    }
+    Ordering::Equal
+}
+
+// Sorts the todos within each category; an empty spec keeps the scan order
+pub fn sort_category_todos(categories: &mut [TodoCategoryData], keys: &[SortKey]) {
+    if keys.is_empty() { return; }
+    for category in categories.iter_mut() {
+        let name = category.name.clone();
+        category.todos.sort_by(|a, b| compare_by_sort_keys(keys, (a, &name), (b, &name)));
+    }
+}
+
+pub fn sort_query_matches(matches: &mut [QueryMatch], keys: &[SortKey]) {
+    if keys.is_empty() { return; }
+    matches.sort_by(|a, b| compare_by_sort_keys(keys, (&a.item, &a.category), (&b.item, &b.category)));
+}
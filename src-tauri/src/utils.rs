@@ -1,4 +1,5 @@
 #![allow(clippy::all)]
+use crate::git_repo::find_git_repo;
 use std::io;
 use std::path::{Path, PathBuf};
 use regex::Regex;
@@ -34,6 +35,23 @@ pub fn generate_short_timestamp() -> String {
     base64_timestamp
 }
 
+// A new `#nanoid` id (20 url-safe characters), including its sigil
+pub fn generate_nanoid_id() -> String {
+    format!("#{}", nanoid::nanoid!(20))
+}
+
+// Inverse of generate_short_timestamp: unix seconds of a 5-char short timestamp (without its `@`/`@@`)
+pub fn decode_short_timestamp(short_timestamp: &str) -> Option<u64> {
+    let url_safe_base64_chars = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+    let custom_epoch: u64 = 1735689600; // Jan 1, 2025, 00:00:00 UTC
+    if short_timestamp.chars().count() != 5 { return None; }
+    let mut seconds_since_custom_epoch: u64 = 0;
+    for c in short_timestamp.chars() {
+        seconds_since_custom_epoch = (seconds_since_custom_epoch << 6) | url_safe_base64_chars.find(c)? as u64;
+    }
+    Some(custom_epoch + seconds_since_custom_epoch)
+}
+
 pub fn get_parent_dir(p: &Path) -> Option<PathBuf> {
     if p.is_file() {
         p.parent().map(|pd| pd.to_path_buf())
@@ -44,24 +62,9 @@ pub fn get_parent_dir(p: &Path) -> Option<PathBuf> {
     }
 }
 
+// Working tree root of the repository containing `start_path`; see `git_repo` for the layouts understood
 pub fn find_git_repo_root(start_path: &Path) -> io::Result<Option<PathBuf>> {
-    let mut current_path = get_parent_dir(start_path)
-        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid start path for git root search. Cannot get parent directory."))?;
-    loop {
-        let git_dir = current_path.join(".git");
-        if git_dir.exists() && git_dir.is_dir() {
-            return Ok(Some(current_path));
-        }
-        if let Some(parent) = current_path.parent() {
-            if parent == current_path { // Reached root or a loop detected
-                break;
-            }
-            current_path = parent.to_path_buf();
-        } else {
-            break; // No parent, reached filesystem root
-        }
-    }
-    Ok(None)
+    Ok(find_git_repo(start_path)?.map(|repo| repo.root))
 }
 
 pub fn extract_cleaned_content_from_line(
@@ -94,6 +97,61 @@ pub fn extract_cleaned_content_from_line(
     }
 }
 
+// Max number of lines after a todo that can be attached to it as continuation lines
+pub const MAX_CONTINUATION_LINES: usize = 20;
+
+fn is_markdown_list_item(trimmed_line: &str) -> bool {
+    if trimmed_line.starts_with("- ") || trimmed_line.starts_with("* ") || trimmed_line.starts_with("+ ") {
+        return true;
+    }
+    let digits_len = trimmed_line.chars().take_while(|c| c.is_ascii_digit()).count();
+    digits_len > 0 && (trimmed_line[digits_len..].starts_with(". ") || trimmed_line[digits_len..].starts_with(") "))
+}
+
+// Collects the continuation lines of the todo on `head_line` (whose marker starts at byte `marker_start`).
+// With a comment leader (e.g. `// TODO: x`), following lines must repeat the same leader at the same indentation
+// and indent their text deeper than the marker. Without one (markdown checklists, bare markers), following lines
+// must be indented deeper than the head and not be list items. Collection stops at the first line that is not a
+// continuation, that is itself a todo, or that carries an ignore directive. Returns the texts with leaders stripped.
+pub fn collect_continuation_lines<'a, I>(head_line: &str, marker_start: usize, following_lines: I, todo_pattern_re: &Regex) -> Vec<String>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let prefix = &head_line[..marker_start];
+    let indent_len = prefix.len() - prefix.trim_start().len();
+    let indent = &prefix[..indent_len];
+    let leader = prefix.trim();
+    let head_gap_len = prefix.len() - indent_len - leader.len();
+
+    let mut continuation = Vec::new();
+    for line in following_lines.into_iter().take(MAX_CONTINUATION_LINES) {
+        let line = line.trim_end();
+        if line.trim().is_empty() || line.contains("UNITODO_IGNORE") || todo_pattern_re.is_match(line) {
+            break;
+        }
+        let text = if leader.is_empty() {
+            let line_indent_len = line.len() - line.trim_start().len();
+            let trimmed = line.trim_start();
+            if line_indent_len <= indent_len || is_markdown_list_item(trimmed) {
+                break;
+            }
+            trimmed
+        } else {
+            let after_leader = match line.strip_prefix(indent).and_then(|rest| rest.strip_prefix(leader)) {
+                Some(rest) => rest,
+                None => break,
+            };
+            let gap_len = after_leader.len() - after_leader.trim_start().len();
+            if after_leader.trim().is_empty() || gap_len <= head_gap_len {
+                break;
+            }
+            after_leader.trim_start()
+        };
+        continuation.push(text.to_string());
+    }
+    continuation
+}
+
 // sort according to "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz-_ "
 pub fn get_char_rank(c: char) -> u8 {
     if c.is_ascii_digit() { c as u8 - b'0' }
@@ -0,0 +1,33 @@
+#![allow(clippy::all)]
+use crate::config_models::{Config, ViewConfig};
+use crate::grouping::{order_view_groups, parse_group_by, GroupContext};
+use crate::todo_models::{ProcessedTodosOutput, ViewGroup};
+use crate::todo_query::{parse_query, query_todos};
+use crate::todo_sort::{parse_sort_spec, sort_query_matches};
+
+use chrono::NaiveDateTime;
+use std::io;
+
+// --- Saved views: queries from the profile config, evaluated against a scan and grouped for display ---
+
+pub fn find_view<'a>(config: &'a Config, name: &str) -> Option<&'a ViewConfig> {
+    config.views.iter().find(|view| view.name == name)
+}
+
+// Todos of a scan matching `view`, sorted by its sort keys, grouped by its `group_by` and ordered like categories.
+// Deferred todos are left out unless `include_deferred` is set or the query asks for them.
+pub fn evaluate_view(processed: &ProcessedTodosOutput, config: &Config, view: &ViewConfig, include_deferred: bool, now: &NaiveDateTime) -> io::Result<Vec<ViewGroup>> {
+    let query = parse_query(&view.query, now)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("View '{}': {}", view.name, e)))?;
+    let sort_keys = parse_sort_spec(&view.sort)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("View '{}': {}", view.name, e)))?;
+    let group_by = parse_group_by(&view.group_by)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("View '{}': {}", view.name, e)))?;
+    let keep_deferred = include_deferred || query.asks_for_deferred();
+    let mut matches: Vec<_> = query_todos(processed, config, &query).into_iter().filter(|m| keep_deferred || !m.item.deferred).collect();
+    sort_query_matches(&mut matches, &sort_keys);
+
+    let mut groups = GroupContext::new(config, &processed.categories, &view.name, *now).group_matches(matches, group_by);
+    order_view_groups(&mut groups, &config.category_order, config);
+    Ok(groups)
+}